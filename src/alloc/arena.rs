@@ -0,0 +1,120 @@
+use std::ffi::CStr;
+use std::marker::PhantomData;
+
+use super::ctl::read;
+use super::{params, JemallocError};
+
+/// Returns whether background threads are enabled.
+pub fn background_threads_enabled() -> Result<bool, JemallocError> {
+    params::background_thread()
+}
+
+/// Enables or disables background threads which purge unused dirty pages.
+///
+/// `max` limits the number of background threads if specified.
+pub fn set_background_threads(enabled: bool, max: Option<usize>) -> Result<(), JemallocError> {
+    if let Some(max) = max {
+        params::set_max_background_threads(max)?;
+    }
+    params::set_background_thread(enabled)
+}
+
+/// Jemalloc arena.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Arena(u32);
+
+impl Arena {
+    /// Creates a new arena with default settings.
+    ///
+    /// Arenas are never destroyed, so they should be created once
+    /// (e.g. for a long-living cache) and reused.
+    pub fn create() -> Result<Self, JemallocError> {
+        let name = CStr::from_bytes_with_nul(b"arenas.create\0").expect("shouldn't fail");
+        read::<u32>(name).map(Self)
+    }
+
+    /// Returns the arena associated with the calling thread.
+    pub fn current() -> Result<Self, JemallocError> {
+        params::thread_arena().map(Self)
+    }
+
+    pub const fn from_index(index: u32) -> Self {
+        Self(index)
+    }
+
+    pub const fn index(&self) -> u32 {
+        self.0
+    }
+
+    /// Associates the calling thread with this arena.
+    pub fn bind_current_thread(&self) -> Result<(), JemallocError> {
+        params::set_thread_arena(self.0)
+    }
+
+    /// Associates the calling thread with this arena until the guard is dropped.
+    pub fn enter(&self) -> Result<ArenaGuard, JemallocError> {
+        let prev = Self::current()?;
+        self.bind_current_thread()?;
+        Ok(ArenaGuard {
+            prev,
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Runs the closure with all allocations of the calling thread made in this arena.
+    pub fn scope<F, R>(&self, f: F) -> Result<R, JemallocError>
+    where
+        F: FnOnce() -> R,
+    {
+        let _guard = self.enter()?;
+        Ok(f())
+    }
+}
+
+/// Restores the previous arena of the thread on drop.
+#[must_use = "the previous arena is restored when the guard is dropped"]
+pub struct ArenaGuard {
+    prev: Arena,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for ArenaGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.prev.bind_current_thread() {
+            log::error!("failed to restore thread arena: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::alloc::ctl::read_dynamic;
+
+    #[test]
+    fn dedicated_arena() {
+        let arena = Arena::create().unwrap();
+        let prev = Arena::current().unwrap();
+        assert_ne!(arena, prev);
+
+        let data = arena
+            .scope(|| {
+                assert_eq!(Arena::current().unwrap(), arena);
+                vec![1u8; 1 << 20]
+            })
+            .unwrap();
+        assert_eq!(Arena::current().unwrap(), prev);
+
+        params::set_epoch(1).unwrap();
+        let mapped =
+            read_dynamic::<usize>(format!("stats.arenas.{}.mapped", arena.index())).unwrap();
+        assert!(mapped >= data.len());
+    }
+
+    #[test]
+    fn background_threads() {
+        let enabled = background_threads_enabled().unwrap();
+        set_background_threads(enabled, Some(params::max_background_threads().unwrap())).unwrap();
+        assert_eq!(background_threads_enabled().unwrap(), enabled);
+    }
+}
@@ -0,0 +1,221 @@
+use std::path::{Path, PathBuf};
+
+/// Default mount point of the cgroup filesystem.
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// File with the cgroup membership of the current process.
+pub const PROC_SELF_CGROUP: &str = "/proc/self/cgroup";
+
+/// Values at or above this are used by cgroup v1 to represent "no limit".
+const CGROUP_V1_UNLIMITED: u64 = 1 << 62;
+
+/// Reads the memory limit of the current process from [`CGROUP_ROOT`].
+pub fn cgroup_memory_limit() -> std::io::Result<Option<u64>> {
+    cgroup_memory_limit_at(CGROUP_ROOT, PROC_SELF_CGROUP)
+}
+
+/// Reads the memory limit of the process from the cgroup filesystem
+/// mounted at `root`.
+///
+/// The cgroup of the process is resolved from the `membership` file
+/// (see [`PROC_SELF_CGROUP`]) and the smallest limit on the path from it up
+/// to `root` is returned. Supports both the unified hierarchy (`memory.max`)
+/// and cgroup v1 (`memory/<path>/memory.limit_in_bytes`). If the membership
+/// file doesn't exist or the process cgroup is not visible under `root`
+/// (e.g. inside a container), only the limits of `root` itself are used.
+/// Returns `None` if there is no limit.
+pub fn cgroup_memory_limit_at<R, M>(root: R, membership: M) -> std::io::Result<Option<u64>>
+where
+    R: AsRef<Path>,
+    M: AsRef<Path>,
+{
+    let membership = match std::fs::read_to_string(membership) {
+        Ok(membership) => Some(membership),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    memory_limit(root.as_ref(), membership.as_deref())
+}
+
+fn memory_limit(root: &Path, membership: Option<&str>) -> std::io::Result<Option<u64>> {
+    let membership = membership.map(Membership::parse).unwrap_or_default();
+
+    let v2 = read_hierarchy(root, membership.unified, "memory.max")?;
+    if !v2.is_empty() {
+        return v2.iter().try_fold(None, |limit, value| {
+            let value = match value.trim() {
+                "max" => None,
+                value => Some(parse_limit(value)?),
+            };
+            Ok(min_limit(limit, value))
+        });
+    }
+
+    let v1 = read_hierarchy(
+        &root.join("memory"),
+        membership.memory,
+        "memory.limit_in_bytes",
+    )?;
+    if v1.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("no cgroup memory limit found in {}", root.display()),
+        ));
+    }
+
+    v1.iter().try_fold(None, |limit, value| {
+        let value = parse_limit(value.trim())?;
+        Ok(min_limit(
+            limit,
+            (value < CGROUP_V1_UNLIMITED).then_some(value),
+        ))
+    })
+}
+
+/// Cgroup paths of the process, relative to the hierarchy root.
+#[derive(Default)]
+struct Membership<'a> {
+    unified: Option<&'a str>,
+    memory: Option<&'a str>,
+}
+
+impl<'a> Membership<'a> {
+    /// Parses `hierarchy-ID:controller-list:cgroup-path` lines.
+    fn parse(content: &'a str) -> Self {
+        let mut membership = Self::default();
+        for line in content.lines() {
+            let mut parts = line.splitn(3, ':');
+            let (id, controllers, path) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(id), Some(controllers), Some(path)) => (id, controllers, path),
+                _ => continue,
+            };
+
+            if id == "0" && controllers.is_empty() {
+                membership.unified = Some(path);
+            } else if controllers.split(',').any(|name| name == "memory") {
+                membership.memory = Some(path);
+            }
+        }
+        membership
+    }
+}
+
+/// Reads `file` in every directory from `base/path` up to `base`,
+/// skipping the missing ones.
+fn read_hierarchy(base: &Path, path: Option<&str>, file: &str) -> std::io::Result<Vec<String>> {
+    let mut dir = base.to_path_buf();
+    dir.extend(
+        PathBuf::from(path.unwrap_or_default())
+            .components()
+            .filter(|component| matches!(component, std::path::Component::Normal(_))),
+    );
+
+    let mut values = Vec::new();
+    loop {
+        match std::fs::read_to_string(dir.join(file)) {
+            Ok(value) => values.push(value),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        if dir == base || !dir.pop() {
+            break;
+        }
+    }
+    Ok(values)
+}
+
+fn min_limit(left: Option<u64>, right: Option<u64>) -> Option<u64> {
+    match (left, right) {
+        (Some(left), Some(right)) => Some(left.min(right)),
+        (left, right) => left.or(right),
+    }
+}
+
+fn parse_limit(value: &str) -> std::io::Result<u64> {
+    value
+        .parse()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_cgroup_limits() {
+        let proc = tempfile::tempdir().unwrap();
+        let membership = proc.path().join("cgroup");
+
+        let v2 = tempfile::tempdir().unwrap();
+        let limit = |root: &Path| cgroup_memory_limit_at(root, &membership);
+        std::fs::write(v2.path().join("memory.max"), "1073741824\n").unwrap();
+        assert_eq!(limit(v2.path()).unwrap(), Some(1 << 30));
+        std::fs::write(v2.path().join("memory.max"), "max\n").unwrap();
+        assert_eq!(limit(v2.path()).unwrap(), None);
+
+        let v1 = tempfile::tempdir().unwrap();
+        std::fs::create_dir(v1.path().join("memory")).unwrap();
+        let limit_path = v1.path().join("memory/memory.limit_in_bytes");
+        std::fs::write(&limit_path, "536870912\n").unwrap();
+        assert_eq!(limit(v1.path()).unwrap(), Some(1 << 29));
+        std::fs::write(&limit_path, "9223372036854771712\n").unwrap();
+        assert_eq!(limit(v1.path()).unwrap(), None);
+
+        let empty = tempfile::tempdir().unwrap();
+        assert!(limit(empty.path()).is_err());
+
+        // Membership is resolved from the specified file
+        let service = v2.path().join("node.service");
+        std::fs::create_dir(&service).unwrap();
+        std::fs::write(service.join("memory.max"), "268435456\n").unwrap();
+        assert_eq!(limit(v2.path()).unwrap(), None);
+        std::fs::write(&membership, "0::/node.service\n").unwrap();
+        assert_eq!(limit(v2.path()).unwrap(), Some(1 << 28));
+    }
+
+    #[test]
+    fn read_process_cgroup_limits() {
+        let v2 = tempfile::tempdir().unwrap();
+        let service = v2.path().join("system.slice/node.service");
+        std::fs::create_dir_all(&service).unwrap();
+        std::fs::write(service.join("memory.max"), "max\n").unwrap();
+        std::fs::write(v2.path().join("system.slice/memory.max"), "1073741824\n").unwrap();
+
+        let membership = "0::/system.slice/node.service\n";
+        assert_eq!(
+            memory_limit(v2.path(), Some(membership)).unwrap(),
+            Some(1 << 30)
+        );
+        std::fs::write(service.join("memory.max"), "536870912\n").unwrap();
+        assert_eq!(
+            memory_limit(v2.path(), Some(membership)).unwrap(),
+            Some(1 << 29)
+        );
+
+        // Not visible from the namespace, only the root is used
+        std::fs::write(v2.path().join("memory.max"), "268435456\n").unwrap();
+        let membership = "0::/docker/abcdef\n";
+        assert_eq!(
+            memory_limit(v2.path(), Some(membership)).unwrap(),
+            Some(1 << 28)
+        );
+
+        let v1 = tempfile::tempdir().unwrap();
+        let container = v1.path().join("memory/docker/abcdef");
+        std::fs::create_dir_all(&container).unwrap();
+        std::fs::write(container.join("memory.limit_in_bytes"), "536870912\n").unwrap();
+        std::fs::write(
+            v1.path().join("memory/memory.limit_in_bytes"),
+            "9223372036854771712\n",
+        )
+        .unwrap();
+
+        let membership =
+            "12:cpu,cpuacct:/docker/other\n4:memory:/docker/abcdef\n1:name=systemd:/\n";
+        assert_eq!(
+            memory_limit(v1.path(), Some(membership)).unwrap(),
+            Some(1 << 29)
+        );
+        assert_eq!(memory_limit(v1.path(), None).unwrap(), None);
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt::Write;
+
+use super::ctl::write;
+use super::{params, set_dirty_decay_ms, set_muzzy_decay_ms, JemallocError};
+
+/// Typed jemalloc options.
+///
+/// Can be rendered as a `MALLOC_CONF` string (see [`JemallocConfig::to_malloc_conf`])
+/// or partially applied at runtime (see [`JemallocConfig::apply`]).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct JemallocConfig {
+    /// Whether to use background threads for purging.
+    pub background_thread: Option<bool>,
+    /// Max number of background threads.
+    pub max_background_threads: Option<usize>,
+    /// Max number of arenas. Startup only.
+    pub narenas: Option<u32>,
+    /// Dirty pages decay time in ms (`-1` disables purging).
+    pub dirty_decay_ms: Option<isize>,
+    /// Muzzy pages decay time in ms (`-1` disables purging).
+    pub muzzy_decay_ms: Option<isize>,
+    /// Whether thread-specific caching is enabled. Startup only.
+    pub tcache: Option<bool>,
+    /// Max size class to cache in the thread-specific cache. Startup only.
+    pub tcache_max: Option<usize>,
+    /// Whether memory profiling is enabled. Startup only.
+    pub prof: Option<bool>,
+    /// Whether sampling is active.
+    pub prof_active: Option<bool>,
+    /// Average interval (log base 2) between allocation samples.
+    pub lg_prof_sample: Option<usize>,
+    /// Average interval (log base 2) between profile dumps. Startup only.
+    pub lg_prof_interval: Option<isize>,
+    /// Whether to dump a profile each time the total virtual memory exceeds
+    /// the previous max.
+    pub prof_gdump: Option<bool>,
+    /// Filename prefix for profile dumps. Startup only.
+    /// Must not contain `,` or `:`.
+    pub prof_prefix: Option<String>,
+}
+
+impl JemallocConfig {
+    /// Renders options as a `MALLOC_CONF` string (e.g. `background_thread:true,narenas:4`).
+    ///
+    /// The string can be passed through the `MALLOC_CONF` env variable
+    /// or the `malloc_conf` symbol. On platforms where jemalloc symbols
+    /// are prefixed (e.g. macOS), they are named `_RJEM_MALLOC_CONF`
+    /// and `_rjem_malloc_conf` instead.
+    ///
+    /// Fails with [`JemallocError::InvalidValue`] if `prof_prefix` contains
+    /// a separator (`,` or `:`).
+    pub fn to_malloc_conf(&self) -> Result<String, JemallocError> {
+        if let Some(prefix) = &self.prof_prefix {
+            if prefix.contains([',', ':']) {
+                return Err(JemallocError::InvalidValue("prof_prefix".to_owned()));
+            }
+        }
+
+        let mut result = String::new();
+        let mut push = |name: &str, value: &dyn std::fmt::Display| {
+            if !result.is_empty() {
+                result.push(',');
+            }
+            write!(result, "{name}:{value}").ok();
+        };
+
+        macro_rules! push_options {
+            ($($name:ident),*$(,)?) => {
+                $(if let Some(value) = &self.$name {
+                    push(stringify!($name), value);
+                })*
+            };
+        }
+
+        push_options!(
+            background_thread,
+            max_background_threads,
+            narenas,
+            dirty_decay_ms,
+            muzzy_decay_ms,
+            tcache,
+            tcache_max,
+            prof,
+            prof_active,
+            lg_prof_sample,
+            lg_prof_interval,
+            prof_gdump,
+            prof_prefix,
+        );
+        Ok(result)
+    }
+
+    /// Applies runtime-settable options.
+    ///
+    /// Startup only options which differ from the current ones are logged.
+    /// Profiling options are ignored unless profiling was enabled at startup.
+    pub fn apply(&self) -> Result<(), JemallocError> {
+        if let Some(max) = self.max_background_threads {
+            params::set_max_background_threads(max)?;
+        }
+        if let Some(enabled) = self.background_thread {
+            params::set_background_thread(enabled)?;
+        }
+        if let Some(ms) = self.dirty_decay_ms {
+            set_dirty_decay_ms(ms)?;
+        }
+        if let Some(ms) = self.muzzy_decay_ms {
+            set_muzzy_decay_ms(ms)?;
+        }
+
+        let prof_enabled = params::opt_prof().unwrap_or_default();
+        if prof_enabled {
+            if let Some(lg_sample) = self.lg_prof_sample {
+                if params::prof_lg_sample()? != lg_sample {
+                    let name = std::ffi::CStr::from_bytes_with_nul(b"prof.reset\0")
+                        .expect("shouldn't fail");
+                    write(name, lg_sample)?;
+                }
+            }
+            if let Some(active) = self.prof_active {
+                params::set_prof_active(active)?;
+            }
+            if let Some(gdump) = self.prof_gdump {
+                params::set_prof_gdump(gdump)?;
+            }
+        }
+
+        let warn_startup_only = |name: &str| {
+            log::warn!("jemalloc option `{name}` can only be set at startup with `MALLOC_CONF`");
+        };
+        if matches!(self.narenas, Some(n) if n != params::opt_narenas()?) {
+            warn_startup_only("narenas");
+        }
+        if matches!(self.tcache, Some(tcache) if tcache != params::opt_tcache()?) {
+            warn_startup_only("tcache");
+        }
+        if self.tcache_max.is_some() {
+            warn_startup_only("tcache_max");
+        }
+        if matches!(self.prof, Some(prof) if prof != prof_enabled) {
+            warn_startup_only("prof");
+        }
+        if !prof_enabled
+            && (self.prof_active == Some(true)
+                || self.lg_prof_sample.is_some()
+                || self.prof_gdump == Some(true))
+        {
+            warn_startup_only("prof");
+        }
+        if self.lg_prof_interval.is_some() {
+            warn_startup_only("lg_prof_interval");
+        }
+        if self.prof_prefix.is_some() {
+            warn_startup_only("prof_prefix");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_malloc_conf() {
+        assert_eq!(JemallocConfig::default().to_malloc_conf().unwrap(), "");
+
+        let mut config = JemallocConfig {
+            background_thread: Some(true),
+            narenas: Some(4),
+            dirty_decay_ms: Some(-1),
+            prof: Some(true),
+            lg_prof_sample: Some(19),
+            prof_prefix: Some("/tmp/jeprof".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.to_malloc_conf().unwrap(),
+            "background_thread:true,narenas:4,dirty_decay_ms:-1,prof:true,lg_prof_sample:19,prof_prefix:/tmp/jeprof"
+        );
+
+        for prefix in ["/tmp/a,b", "C:/jeprof"] {
+            config.prof_prefix = Some(prefix.to_owned());
+            assert!(matches!(
+                config.to_malloc_conf(),
+                Err(JemallocError::InvalidValue(name)) if name == "prof_prefix"
+            ));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn parse_config() {
+        let config = serde_json::from_str::<JemallocConfig>(
+            r#"{ "background_thread": true, "muzzy_decay_ms": 0 }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.to_malloc_conf().unwrap(),
+            "background_thread:true,muzzy_decay_ms:0"
+        );
+
+        assert!(serde_json::from_str::<JemallocConfig>(r#"{ "unknown": 1 }"#).is_err());
+    }
+
+    #[test]
+    fn apply_current_values() {
+        let config = JemallocConfig {
+            max_background_threads: Some(params::max_background_threads().unwrap()),
+            narenas: Some(params::opt_narenas().unwrap()),
+            ..Default::default()
+        };
+        config.apply().unwrap();
+    }
+}
@@ -0,0 +1,298 @@
+use std::ffi::{c_void, CStr, CString};
+
+/// Arena index which refers to all arenas at once (e.g. in `arena.<i>.purge`).
+pub const MALLCTL_ARENAS_ALL: u32 = 4096;
+
+mod sealed {
+    /// Types with the same layout as the corresponding C types used by jemalloc.
+    pub trait RawValue: Copy + 'static {}
+
+    impl RawValue for bool {}
+    impl RawValue for i32 {}
+    impl RawValue for u32 {}
+    impl RawValue for i64 {}
+    impl RawValue for u64 {}
+    impl RawValue for isize {}
+    impl RawValue for usize {}
+    impl RawValue for *mut u64 {}
+}
+
+pub(crate) use self::sealed::RawValue;
+
+/// Plain data types which can be passed to and from `mallctl`.
+///
+/// This trait is sealed.
+pub trait MallctlValue: RawValue {}
+
+impl MallctlValue for bool {}
+impl MallctlValue for i32 {}
+impl MallctlValue for u32 {}
+impl MallctlValue for i64 {}
+impl MallctlValue for u64 {}
+impl MallctlValue for isize {}
+impl MallctlValue for usize {}
+
+/// Reads the jemalloc param with the specified name.
+///
+/// Fails with [`JemallocError::InvalidValue`] if the size of `T`
+/// doesn't match the type of the param. Prefer typed accessors from [`params`].
+///
+/// # Safety
+///
+/// `T` must be the C type of the param, not just a type of the same size
+/// (e.g. `bool` must not be read from a param which can hold other values).
+pub unsafe fn get_jemalloc_param<T: MallctlValue>(name: &str) -> Result<T, JemallocError> {
+    let name = CString::new(name).map_err(|_| JemallocError::InvalidName)?;
+    read(&name)
+}
+
+/// Writes the jemalloc param with the specified name.
+///
+/// Fails with [`JemallocError::InvalidValue`] if the size of `T`
+/// doesn't match the type of the param. Prefer typed setters from [`params`].
+///
+/// # Safety
+///
+/// `T` must be the C type of the param and `value` must be valid for it.
+/// Some params hold pointers or trigger actions (e.g. `arena.<i>.extent_hooks`,
+/// `prof.dump`), jemalloc dereferences integers written to them.
+pub unsafe fn try_set_jemalloc_param<T: MallctlValue>(
+    name: &str,
+    value: T,
+) -> Result<(), JemallocError> {
+    let name = CString::new(name).map_err(|_| JemallocError::InvalidName)?;
+    write(&name, value)
+}
+
+pub(crate) fn read<T: RawValue>(name: &CStr) -> Result<T, JemallocError> {
+    let mut value = std::mem::MaybeUninit::<T>::uninit();
+    let mut len = std::mem::size_of::<T>();
+
+    let res = unsafe {
+        tikv_jemalloc_sys::mallctl(
+            name.as_ptr(),
+            value.as_mut_ptr() as *mut c_void,
+            &mut len,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    check(name, res)?;
+
+    // NOTE: jemalloc fails with `EINVAL` on size mismatch, so this is
+    // only a sanity check
+    if len != std::mem::size_of::<T>() {
+        return Err(JemallocError::InvalidValue(name_to_string(name)));
+    }
+
+    // SAFETY: the value was fully written by `mallctl`
+    Ok(unsafe { value.assume_init() })
+}
+
+pub(crate) fn write<T: RawValue>(name: &CStr, mut value: T) -> Result<(), JemallocError> {
+    let res = unsafe {
+        tikv_jemalloc_sys::mallctl(
+            name.as_ptr(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            &mut value as *mut T as *mut c_void,
+            std::mem::size_of::<T>(),
+        )
+    };
+    check(name, res)
+}
+
+/// Calls the ctl which neither reads nor writes anything (e.g. `arena.0.purge`).
+pub(crate) fn call(name: &CStr) -> Result<(), JemallocError> {
+    let res = unsafe {
+        tikv_jemalloc_sys::mallctl(
+            name.as_ptr(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    check(name, res)
+}
+
+/// Reads the param with a name built at runtime (e.g. `stats.arenas.0.pactive`).
+pub(crate) fn read_dynamic<T: RawValue>(name: String) -> Result<T, JemallocError> {
+    let name = CString::new(name).map_err(|_| JemallocError::InvalidName)?;
+    read(&name)
+}
+
+/// Writes the param with a name built at runtime (e.g. `arena.0.dirty_decay_ms`).
+pub(crate) fn write_dynamic<T: RawValue>(name: String, value: T) -> Result<(), JemallocError> {
+    let name = CString::new(name).map_err(|_| JemallocError::InvalidName)?;
+    write(&name, value)
+}
+
+/// Calls the ctl with a name built at runtime.
+pub(crate) fn call_dynamic(name: String) -> Result<(), JemallocError> {
+    let name = CString::new(name).map_err(|_| JemallocError::InvalidName)?;
+    call(&name)
+}
+
+pub(crate) fn check(name: &CStr, res: i32) -> Result<(), JemallocError> {
+    match res {
+        0 => Ok(()),
+        libc::ENOENT => Err(JemallocError::UnknownParam(name_to_string(name))),
+        libc::EPERM => Err(JemallocError::ReadOnly(name_to_string(name))),
+        libc::EINVAL => Err(JemallocError::InvalidValue(name_to_string(name))),
+        errno => Err(JemallocError::Other {
+            name: name_to_string(name),
+            errno: errno::Errno(errno),
+        }),
+    }
+}
+
+fn name_to_string(name: &CStr) -> String {
+    name.to_string_lossy().into_owned()
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum JemallocError {
+    #[error("invalid param name")]
+    InvalidName,
+    #[error("unknown param `{0}`")]
+    UnknownParam(String),
+    #[error("param `{0}` is read-only")]
+    ReadOnly(String),
+    #[error("invalid value type or value for `{0}`")]
+    InvalidValue(String),
+    #[error("failed to access `{name}`: {errno}")]
+    Other { name: String, errno: errno::Errno },
+}
+
+/// Strongly typed accessors for the known jemalloc params.
+pub mod params {
+    use super::JemallocError;
+
+    macro_rules! declare_params {
+        ($($(#[doc = $doc:literal])* $name:literal => $ty:ty: $get:ident $(, $set:ident)?;)*) => {
+            $(
+                $(#[doc = $doc])*
+                pub fn $get() -> Result<$ty, JemallocError> {
+                    super::read(param_name(concat!($name, "\0")))
+                }
+
+                $(
+                    #[doc = concat!("Sets `", $name, "`.")]
+                    pub fn $set(value: $ty) -> Result<(), JemallocError> {
+                        super::write(param_name(concat!($name, "\0")), value)
+                    }
+                )?
+            )*
+        };
+    }
+
+    fn param_name(name: &'static str) -> &'static std::ffi::CStr {
+        std::ffi::CStr::from_bytes_with_nul(name.as_bytes()).expect("shouldn't fail")
+    }
+
+    declare_params! {
+        /// Whether jemalloc was built with profiling support.
+        "config.prof" => bool: config_prof;
+
+        /// Whether background threads are enabled.
+        "background_thread" => bool: background_thread, set_background_thread;
+        /// Max number of background threads.
+        "max_background_threads" => usize: max_background_threads, set_max_background_threads;
+
+        /// Stats snapshot version. Writing to it refreshes the cached stats.
+        "epoch" => u64: epoch, set_epoch;
+
+        /// Total number of bytes allocated by the application.
+        "stats.allocated" => usize: stats_allocated;
+        /// Total number of bytes in active pages allocated by the application.
+        "stats.active" => usize: stats_active;
+        /// Total number of bytes dedicated to metadata.
+        "stats.metadata" => usize: stats_metadata;
+        /// Number of bytes in physically resident data pages mapped by the allocator.
+        "stats.resident" => usize: stats_resident;
+        /// Total number of bytes in active extents mapped by the allocator.
+        "stats.mapped" => usize: stats_mapped;
+        /// Total number of bytes in virtual memory mappings retained for future reuse.
+        "stats.retained" => usize: stats_retained;
+
+        /// Current number of arenas.
+        "arenas.narenas" => u32: arenas_narenas;
+        /// Page size.
+        "arenas.page" => usize: arenas_page;
+        /// Number of bin size classes.
+        "arenas.nbins" => u32: arenas_nbins;
+        /// Number of large size classes.
+        "arenas.nlextents" => u32: arenas_nlextents;
+        /// Default dirty decay time in ms for new arenas (`-1` disables purging).
+        "arenas.dirty_decay_ms" => isize: arenas_dirty_decay_ms, set_arenas_dirty_decay_ms;
+        /// Default muzzy decay time in ms for new arenas (`-1` disables purging).
+        "arenas.muzzy_decay_ms" => isize: arenas_muzzy_decay_ms, set_arenas_muzzy_decay_ms;
+
+        /// Max number of arenas set at startup.
+        "opt.narenas" => u32: opt_narenas;
+        /// Dirty decay time in ms set at startup.
+        "opt.dirty_decay_ms" => isize: opt_dirty_decay_ms;
+        /// Muzzy decay time in ms set at startup.
+        "opt.muzzy_decay_ms" => isize: opt_muzzy_decay_ms;
+        /// Whether thread-specific caching is enabled at startup.
+        "opt.tcache" => bool: opt_tcache;
+        /// Whether profiling was enabled at startup (`MALLOC_CONF=prof:true`).
+        "opt.prof" => bool: opt_prof;
+        /// Initial value of `prof.active`.
+        "opt.prof_active" => bool: opt_prof_active;
+        /// Average interval (log base 2) between allocation samples set at startup.
+        "opt.lg_prof_sample" => usize: opt_lg_prof_sample;
+
+        /// Whether sampling is active.
+        "prof.active" => bool: prof_active, set_prof_active;
+        /// Whether a profile is dumped each time the total virtual memory exceeds the previous max.
+        "prof.gdump" => bool: prof_gdump, set_prof_gdump;
+        /// Current average interval (log base 2) between allocation samples.
+        "prof.lg_sample" => usize: prof_lg_sample;
+
+        /// Whether the thread cache is enabled for the calling thread.
+        "thread.tcache.enabled" => bool: thread_tcache_enabled, set_thread_tcache_enabled;
+        /// Arena associated with the calling thread.
+        "thread.arena" => u32: thread_arena, set_thread_arena;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_write_params() {
+        assert!(params::arenas_narenas().unwrap() > 0);
+        assert!(unsafe { get_jemalloc_param::<u32>("arenas.narenas") }.unwrap() > 0);
+
+        let decay = params::arenas_dirty_decay_ms().unwrap();
+        params::set_arenas_dirty_decay_ms(decay + 1).unwrap();
+        assert_eq!(params::arenas_dirty_decay_ms().unwrap(), decay + 1);
+        unsafe { try_set_jemalloc_param("arenas.dirty_decay_ms", decay) }.unwrap();
+        assert_eq!(params::arenas_dirty_decay_ms().unwrap(), decay);
+    }
+
+    #[test]
+    fn param_errors() {
+        unsafe {
+            assert!(matches!(
+                get_jemalloc_param::<bool>("arenas.narenas"),
+                Err(JemallocError::InvalidValue(_))
+            ));
+            assert!(matches!(
+                get_jemalloc_param::<u32>("unknown.param"),
+                Err(JemallocError::UnknownParam(_))
+            ));
+            assert!(matches!(
+                try_set_jemalloc_param::<u32>("arenas.narenas", 1),
+                Err(JemallocError::ReadOnly(_))
+            ));
+            assert!(matches!(
+                get_jemalloc_param::<u32>("arenas\0narenas"),
+                Err(JemallocError::InvalidName)
+            ));
+        }
+    }
+}
@@ -0,0 +1,121 @@
+pub use self::arena::{background_threads_enabled, set_background_threads, Arena, ArenaGuard};
+pub use self::cgroup::{
+    cgroup_memory_limit, cgroup_memory_limit_at, CGROUP_ROOT, PROC_SELF_CGROUP,
+};
+pub use self::config::JemallocConfig;
+pub use self::ctl::{
+    get_jemalloc_param, params, try_set_jemalloc_param, JemallocError, MallctlValue,
+    MALLCTL_ARENAS_ALL,
+};
+pub use self::purge::{
+    arena_dirty_decay_ms, arena_muzzy_decay_ms, decay_arena, dirty_bytes, purge_all, purge_arena,
+    set_arena_dirty_decay_ms, set_arena_muzzy_decay_ms, set_dirty_decay_ms, set_muzzy_decay_ms,
+    PurgeReport,
+};
+#[cfg(feature = "alloc-tasks")]
+pub use self::purge::{AutoPurge, AutoPurgeHandle};
+pub use self::stats_print::{stats_print, stats_print_json, StatsPrintOptions};
+pub use self::thread::{
+    measure_allocations, measure_future_allocations, thread_allocated, thread_deallocated,
+    AllocationScope, AllocationStats, MeasureAllocations,
+};
+#[cfg(feature = "alloc-tasks")]
+pub use self::watchdog::{MemoryPressure, MemoryWatchdog, MemoryWatchdogHandle};
+
+mod arena;
+mod cgroup;
+mod config;
+mod ctl;
+#[cfg(all(feature = "alloc-profiling", unix))]
+pub mod profiling;
+mod purge;
+mod stats_print;
+#[cfg(any(
+    feature = "alloc-tasks",
+    all(
+        feature = "alloc-profiling",
+        unix,
+        any(feature = "metrics", feature = "signal")
+    )
+))]
+mod task;
+mod thread;
+#[cfg(feature = "alloc-tasks")]
+mod watchdog;
+
+pub type Allocator = tikv_jemallocator::Jemalloc;
+
+pub const fn allocator() -> Allocator {
+    Allocator {}
+}
+
+/// Writes the jemalloc param, logging failures.
+///
+/// NOTE: only the size of `T` is passed to jemalloc, the value is not checked
+/// against the type of the param. Use typed setters from [`params`]
+/// or [`try_set_jemalloc_param`] instead.
+#[deprecated(note = "use typed setters from `params` or `try_set_jemalloc_param`")]
+pub fn set_jemalloc_param<T>(name: &str, mut value: T) {
+    let name_buffer = match std::ffi::CString::new(name) {
+        Ok(name) => name,
+        Err(_) => {
+            log::error!("Failed to set {name}: {}", JemallocError::InvalidName);
+            return;
+        }
+    };
+
+    let res = unsafe {
+        tikv_jemalloc_sys::mallctl(
+            name_buffer.as_ptr(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            &mut value as *mut T as *mut std::ffi::c_void,
+            std::mem::size_of::<T>(),
+        )
+    };
+
+    if res != 0 {
+        log::error!("Failed to set {name}: {}", errno::Errno(res));
+    }
+}
+
+// run with `MALLOC_CONF="prof:true" cargo test --ignored`
+#[cfg(all(test, feature = "alloc-profiling"))]
+mod test {
+    const OPT_PROF: &[u8] = b"opt.prof\0";
+
+    fn is_profiling_on() -> bool {
+        match unsafe { tikv_jemalloc_ctl::raw::read(OPT_PROF) } {
+            Err(e) => {
+                // Shouldn't be possible since mem-profiling is set
+                panic!("is_profiling_on: {e:?}");
+            }
+            Ok(prof) => prof,
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn test_profile() {
+        use super::*;
+        use tempfile::TempDir;
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("profile.txt");
+
+        profiling::start().unwrap();
+        assert!(is_profiling_on());
+
+        profiling::dump(path.as_path()).unwrap();
+        profiling::stop().unwrap();
+
+        let profile = profiling::HeapProfile::read(&path).unwrap();
+        assert!(profile.bytes > 0);
+        assert!(!profile.stacks.is_empty());
+
+        let mut collapsed = Vec::new();
+        let symbols = profile.symbolize();
+        profile.write_collapsed(&symbols, &mut collapsed).unwrap();
+        assert!(!collapsed.is_empty());
+    }
+}
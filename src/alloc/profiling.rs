@@ -0,0 +1,247 @@
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+pub use tikv_jemalloc_ctl::Error;
+use tikv_jemalloc_ctl::{epoch, stats};
+
+use crate::alloc::{ctl, params, JemallocError};
+
+pub use self::detailed_stats::*;
+pub use self::heap_profile::*;
+#[cfg(feature = "metrics")]
+pub use self::metrics_loop::*;
+pub use self::report::*;
+#[cfg(feature = "signal")]
+pub use self::signals::*;
+pub use self::supervisor::*;
+
+mod detailed_stats;
+mod heap_profile;
+#[cfg(feature = "metrics")]
+mod metrics_loop;
+mod report;
+#[cfg(feature = "signal")]
+mod signals;
+mod supervisor;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JemallocStats {
+    pub allocated: u64,
+    pub active: u64,
+    pub metadata: u64,
+    pub resident: u64,
+    pub mapped: u64,
+    pub retained: u64,
+    pub dirty: u64,
+    pub fragmentation: u64,
+}
+
+impl JemallocStats {
+    /// Share of active memory which is not allocated, in percent.
+    pub fn fragmentation_percent(&self) -> f64 {
+        if self.active == 0 {
+            return 0.0;
+        }
+        self.fragmentation as f64 * 100.0 / self.active as f64
+    }
+
+    /// Ratio of resident memory to allocated memory.
+    pub fn resident_to_allocated_ratio(&self) -> f64 {
+        if self.allocated == 0 {
+            return 0.0;
+        }
+        self.resident as f64 / self.allocated as f64
+    }
+}
+
+/// Exports global allocator stats forever.
+///
+/// Per arena and per size class stats are not exported,
+/// use [`AllocatorMetrics::with_detailed_stats`] to enable them.
+#[cfg(feature = "metrics")]
+pub async fn allocator_metrics_loop() {
+    AllocatorMetrics::default()
+        .with_detailed_stats(false)
+        .run()
+        .await
+}
+
+pub fn fetch_stats() -> Result<JemallocStats, Error> {
+    // Stats are cached. Need to advance epoch to refresh.
+    epoch::advance()?;
+
+    Ok(JemallocStats {
+        allocated: stats::allocated::read()? as u64,
+        active: stats::active::read()? as u64,
+        metadata: stats::metadata::read()? as u64,
+        resident: stats::resident::read()? as u64,
+        mapped: stats::mapped::read()? as u64,
+        retained: stats::retained::read()? as u64,
+        dirty: (stats::resident::read()?
+            .saturating_sub(stats::active::read()?)
+            .saturating_sub(stats::metadata::read()?)) as u64,
+        fragmentation: (stats::active::read()?.saturating_sub(stats::allocated::read()?)) as u64,
+    })
+}
+
+const PROF_ACTIVE: &[u8] = b"prof.active\0";
+const PROF_DUMP: &[u8] = b"prof.dump\0";
+const PROF_RESET: &[u8] = b"prof.reset\0";
+
+/// Activates sampling.
+///
+/// Fails with [`ProfilingError::Disabled`] if profiling was not enabled at startup.
+pub fn start() -> Result<(), ProfilingError> {
+    log::info!("starting profiler");
+    ensure_enabled()?;
+
+    let name = CStr::from_bytes_with_nul(PROF_ACTIVE).expect("shouldn't fail");
+    ctl::write(name, true)?;
+    Ok(())
+}
+
+pub fn stop() -> Result<(), Error> {
+    log::info!("stopping profiler");
+    unsafe { tikv_jemalloc_ctl::raw::update(PROF_ACTIVE, false)? };
+    Ok(())
+}
+
+/// Profiling settings and state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProfilingStatus {
+    /// Whether profiling was enabled at startup (`opt.prof`).
+    pub enabled: bool,
+    /// Whether sampling is active (`prof.active`).
+    pub active: bool,
+    /// Average interval (log base 2) between allocation samples
+    /// set at startup (`opt.lg_prof_sample`).
+    pub opt_lg_sample: usize,
+    /// Current average interval (log base 2) between allocation samples
+    /// (`prof.lg_sample`). `None` if profiling is disabled.
+    pub lg_sample: Option<usize>,
+}
+
+/// Returns profiling settings and state.
+pub fn status() -> Result<ProfilingStatus, ProfilingError> {
+    let enabled = params::opt_prof()?;
+    Ok(ProfilingStatus {
+        enabled,
+        active: enabled && params::prof_active()?,
+        opt_lg_sample: params::opt_lg_prof_sample()?,
+        lg_sample: if enabled {
+            Some(params::prof_lg_sample()?)
+        } else {
+            None
+        },
+    })
+}
+
+/// Fails with [`ProfilingError::Disabled`] if the process was started
+/// without `MALLOC_CONF=prof:true`.
+pub fn ensure_enabled() -> Result<(), ProfilingError> {
+    if params::opt_prof()? {
+        Ok(())
+    } else {
+        Err(ProfilingError::Disabled)
+    }
+}
+
+/// Clears all collected samples and optionally sets a new average
+/// interval (log base 2) between allocation samples.
+pub fn reset(lg_sample: Option<usize>) -> Result<(), ProfilingError> {
+    ensure_enabled()?;
+
+    let name = CStr::from_bytes_with_nul(PROF_RESET).expect("shouldn't fail");
+    match lg_sample {
+        Some(lg_sample) => ctl::write(name, lg_sample)?,
+        None => ctl::call(name)?,
+    }
+    log::info!(
+        "reset the profiler (lg_sample: {})",
+        params::prof_lg_sample()?
+    );
+    Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ProfilingError {
+    #[error("profiling is disabled, restart with `MALLOC_CONF=prof:true`")]
+    Disabled,
+    #[error(transparent)]
+    JemallocError(#[from] JemallocError),
+}
+
+/// Dump the profile to the `path`.
+pub fn dump<P>(path: P) -> Result<(), DumpError>
+where
+    P: AsRef<Path>,
+{
+    if !params::opt_prof().unwrap_or_default() {
+        log::error!("failed to dump the profiling info: profiling is disabled");
+        return Err(DumpError::ProfilingDisabled);
+    }
+
+    let mut bytes = CString::new(path.as_ref().as_os_str().as_bytes())
+        .map_err(|_| DumpError::InvalidPath)?
+        .into_bytes_with_nul();
+
+    let ptr = bytes.as_mut_ptr() as *mut c_char;
+    let res = unsafe { tikv_jemalloc_ctl::raw::write(PROF_DUMP, ptr) };
+    match res {
+        Ok(_) => {
+            log::info!("saved the profiling dump to {:?}", path.as_ref());
+            Ok(())
+        }
+        Err(e) => {
+            log::error!(
+                "failed to dump the profiling info to {:?}: {e:?}",
+                path.as_ref()
+            );
+            Err(DumpError::JemallocError(e.to_string()))
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DumpError {
+    #[error("invalid path to the dump")]
+    InvalidPath,
+    #[error("profiling is disabled, restart with `MALLOC_CONF=prof:true`")]
+    ProfilingDisabled,
+    #[error("failed to dump the profiling info: {0}")]
+    JemallocError(String),
+    #[error("failed to prepare the dump directory")]
+    Io(#[source] std::io::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn status_and_reset() {
+        let status = status().unwrap();
+        if status.enabled {
+            reset(Some(status.opt_lg_sample + 1)).unwrap();
+            assert_eq!(
+                super::status().unwrap().lg_sample,
+                Some(status.opt_lg_sample + 1)
+            );
+            reset(Some(status.opt_lg_sample)).unwrap();
+        } else {
+            assert!(!status.active);
+            assert_eq!(status.lg_sample, None);
+            assert!(matches!(ensure_enabled(), Err(ProfilingError::Disabled)));
+            assert!(matches!(reset(None), Err(ProfilingError::Disabled)));
+            assert!(matches!(start(), Err(ProfilingError::Disabled)));
+            assert!(matches!(
+                dump("/tmp/heap.prof"),
+                Err(DumpError::ProfilingDisabled)
+            ));
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use crate::alloc::ctl::read_dynamic;
+use crate::alloc::{params, JemallocError, MALLCTL_ARENAS_ALL};
+
+/// Per-arena and per-size-class jemalloc stats.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DetailedJemallocStats {
+    pub page_size: u64,
+    /// Stats of all initialized arenas.
+    pub arenas: Vec<ArenaStats>,
+    /// Small size classes stats merged across all arenas.
+    pub bins: Vec<BinStats>,
+    /// Large size classes stats merged across all arenas.
+    pub large_extents: Vec<LargeExtentStats>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArenaStats {
+    pub index: u32,
+    /// Number of pages in active extents.
+    pub pactive: u64,
+    /// Number of pages within unused extents that are potentially dirty.
+    pub pdirty: u64,
+    /// Number of pages within unused extents that are muzzy.
+    pub pmuzzy: u64,
+    /// Number of mapped bytes.
+    pub mapped: u64,
+    /// Number of retained bytes.
+    pub retained: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BinStats {
+    /// Size class in bytes.
+    pub size: u64,
+    /// Number of regions per slab.
+    pub nregs: u32,
+    /// Cumulative number of allocations.
+    pub nmalloc: u64,
+    /// Cumulative number of deallocations.
+    pub ndalloc: u64,
+    /// Cumulative number of allocation requests (including tcache hits).
+    pub nrequests: u64,
+    /// Current number of allocated regions.
+    pub curregs: u64,
+    /// Current number of slabs.
+    pub curslabs: u64,
+    /// Current number of slabs with free regions.
+    pub nonfull_slabs: u64,
+    /// Fraction of allocated regions in the current slabs.
+    pub utilization: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LargeExtentStats {
+    /// Size class in bytes.
+    pub size: u64,
+    /// Cumulative number of allocations.
+    pub nmalloc: u64,
+    /// Cumulative number of deallocations.
+    pub ndalloc: u64,
+    /// Current number of extents.
+    pub curlextents: u64,
+}
+
+pub fn fetch_detailed_stats() -> Result<DetailedJemallocStats, JemallocError> {
+    // Stats are cached. Need to advance epoch to refresh.
+    params::set_epoch(1)?;
+
+    let mut arenas = Vec::new();
+    for i in 0..params::arenas_narenas()? {
+        if !read_dynamic::<bool>(format!("arena.{i}.initialized"))? {
+            continue;
+        }
+
+        let stat = |name: &str| read_dynamic::<usize>(format!("stats.arenas.{i}.{name}"));
+        arenas.push(ArenaStats {
+            index: i,
+            pactive: stat("pactive")? as u64,
+            pdirty: stat("pdirty")? as u64,
+            pmuzzy: stat("pmuzzy")? as u64,
+            mapped: stat("mapped")? as u64,
+            retained: stat("retained")? as u64,
+        });
+    }
+
+    let mut bins = Vec::new();
+    for j in 0..params::arenas_nbins()? {
+        let u64_stat = |name: &str| {
+            read_dynamic::<u64>(format!("stats.arenas.{MALLCTL_ARENAS_ALL}.bins.{j}.{name}"))
+        };
+        let usize_stat = |name: &str| {
+            read_dynamic::<usize>(format!("stats.arenas.{MALLCTL_ARENAS_ALL}.bins.{j}.{name}"))
+                .map(|value| value as u64)
+        };
+
+        let nregs = read_dynamic::<u32>(format!("arenas.bin.{j}.nregs"))?;
+        let curregs = usize_stat("curregs")?;
+        let curslabs = usize_stat("curslabs")?;
+        let capacity = curslabs * nregs as u64;
+
+        bins.push(BinStats {
+            size: read_dynamic::<usize>(format!("arenas.bin.{j}.size"))? as u64,
+            nregs,
+            nmalloc: u64_stat("nmalloc")?,
+            ndalloc: u64_stat("ndalloc")?,
+            nrequests: u64_stat("nrequests")?,
+            curregs,
+            curslabs,
+            nonfull_slabs: usize_stat("nonfull_slabs")?,
+            utilization: if capacity > 0 {
+                curregs as f64 / capacity as f64
+            } else {
+                0.0
+            },
+        });
+    }
+
+    let mut large_extents = Vec::new();
+    for j in 0..params::arenas_nlextents()? {
+        let stat = |name: &str| format!("stats.arenas.{MALLCTL_ARENAS_ALL}.lextents.{j}.{name}");
+
+        large_extents.push(LargeExtentStats {
+            size: read_dynamic::<usize>(format!("arenas.lextent.{j}.size"))? as u64,
+            nmalloc: read_dynamic::<u64>(stat("nmalloc"))?,
+            ndalloc: read_dynamic::<u64>(stat("ndalloc"))?,
+            curlextents: read_dynamic::<usize>(stat("curlextents"))? as u64,
+        });
+    }
+
+    Ok(DetailedJemallocStats {
+        page_size: params::arenas_page()? as u64,
+        arenas,
+        bins,
+        large_extents,
+    })
+}
+
+#[cfg(feature = "metrics")]
+impl DetailedJemallocStats {
+    /// Exports stats as gauges and counters labeled by `arena` or `size_class`.
+    ///
+    /// Metric names start with `prefix` and all of them get the static `labels`.
+    /// Size classes which were never used are skipped.
+    pub fn export_metrics(&self, prefix: &str, labels: &[metrics::Label]) {
+        let with_label = |key: &'static str, value: String| {
+            let mut labels = labels.to_vec();
+            labels.push(metrics::Label::new(key, value));
+            labels
+        };
+
+        for arena in &self.arenas {
+            let labels = with_label("arena", arena.index.to_string());
+            metrics::gauge!(format!("{prefix}_arena_active_bytes"), labels.clone())
+                .set((arena.pactive * self.page_size) as f64);
+            metrics::gauge!(format!("{prefix}_arena_dirty_bytes"), labels.clone())
+                .set((arena.pdirty * self.page_size) as f64);
+            metrics::gauge!(format!("{prefix}_arena_muzzy_bytes"), labels.clone())
+                .set((arena.pmuzzy * self.page_size) as f64);
+            metrics::gauge!(format!("{prefix}_arena_mapped_bytes"), labels.clone())
+                .set(arena.mapped as f64);
+            metrics::gauge!(format!("{prefix}_arena_retained_bytes"), labels)
+                .set(arena.retained as f64);
+        }
+
+        for bin in self.bins.iter().filter(|bin| bin.nmalloc > 0) {
+            let labels = with_label("size_class", bin.size.to_string());
+            metrics::counter!(format!("{prefix}_bin_allocations_total"), labels.clone())
+                .absolute(bin.nmalloc);
+            metrics::counter!(format!("{prefix}_bin_deallocations_total"), labels.clone())
+                .absolute(bin.ndalloc);
+            metrics::gauge!(format!("{prefix}_bin_regions"), labels.clone())
+                .set(bin.curregs as f64);
+            metrics::gauge!(format!("{prefix}_bin_slabs"), labels.clone()).set(bin.curslabs as f64);
+            metrics::gauge!(format!("{prefix}_bin_utilization"), labels).set(bin.utilization);
+        }
+
+        for extent in self
+            .large_extents
+            .iter()
+            .filter(|extent| extent.nmalloc > 0)
+        {
+            let labels = with_label("size_class", extent.size.to_string());
+            metrics::counter!(format!("{prefix}_large_allocations_total"), labels.clone())
+                .absolute(extent.nmalloc);
+            metrics::counter!(
+                format!("{prefix}_large_deallocations_total"),
+                labels.clone()
+            )
+            .absolute(extent.ndalloc);
+            metrics::gauge!(format!("{prefix}_large_extents"), labels)
+                .set(extent.curlextents as f64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detailed_stats() {
+        let data = vec![0u8; 1 << 20];
+        let stats = fetch_detailed_stats().unwrap();
+        drop(data);
+
+        assert!(stats.page_size > 0);
+        assert!(!stats.arenas.is_empty());
+        assert!(stats.bins.windows(2).all(|w| w[0].size < w[1].size));
+        assert!(stats.bins.iter().any(|bin| bin.curregs > 0));
+        assert!(stats
+            .large_extents
+            .iter()
+            .any(|extent| extent.size >= 1 << 20 && extent.curlextents > 0));
+    }
+}
@@ -0,0 +1,380 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+
+mod pprof;
+
+/// Parsed jemalloc heap profile (`heap_v2` format produced by [`dump`]).
+///
+/// Values are estimated from samples the same way as `jeprof` does it.
+///
+/// [`dump`]: super::dump
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeapProfile {
+    /// Average interval in bytes between allocation samples.
+    pub sample_period: u64,
+    /// Estimated number of live objects.
+    pub objects: i64,
+    /// Estimated size of live objects in bytes.
+    pub bytes: i64,
+    /// Allocation stacks sorted by addresses.
+    pub stacks: Vec<HeapStack>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeapStack {
+    /// Return addresses starting from the innermost frame.
+    pub addresses: Vec<u64>,
+    /// Estimated number of live objects.
+    pub objects: i64,
+    /// Estimated size of live objects in bytes.
+    pub bytes: i64,
+}
+
+impl HeapProfile {
+    /// Reads and parses the heap profile.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, HeapProfileError> {
+        let data = std::fs::read_to_string(path).map_err(HeapProfileError::Io)?;
+        Self::parse(&data)
+    }
+
+    /// Parses the heap profile, merging identical stacks.
+    pub fn parse(data: &str) -> Result<Self, HeapProfileError> {
+        let mut lines = data.lines().enumerate();
+
+        let sample_period = match lines.next() {
+            Some((_, header)) => header
+                .strip_prefix("heap_v2/")
+                .and_then(|period| period.trim().parse::<u64>().ok())
+                .ok_or(HeapProfileError::InvalidHeader)?,
+            None => return Err(HeapProfileError::InvalidHeader),
+        };
+
+        let mut profile = HeapProfile {
+            sample_period,
+            ..Default::default()
+        };
+        let mut stacks = BTreeMap::<Vec<u64>, (i64, i64)>::new();
+        let mut current = None::<Vec<u64>>;
+
+        for (i, line) in lines {
+            let invalid_line = || HeapProfileError::InvalidLine(i + 1);
+
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            } else if line.starts_with("MAPPED_LIBRARIES:") {
+                break;
+            } else if let Some(addresses) = line.strip_prefix('@') {
+                let addresses = addresses
+                    .split_whitespace()
+                    .map(parse_address)
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(invalid_line)?;
+                current = Some(addresses);
+            } else if let Some(counts) = line.strip_prefix("t*:") {
+                let (objects, bytes) = parse_counts(counts).ok_or_else(invalid_line)?;
+                let (objects, bytes) = unsample(objects, bytes, sample_period);
+                match current.take() {
+                    Some(addresses) => {
+                        let entry = stacks.entry(addresses).or_default();
+                        entry.0 += objects;
+                        entry.1 += bytes;
+                    }
+                    None => {
+                        profile.objects = objects;
+                        profile.bytes = bytes;
+                    }
+                }
+            } else if !line.starts_with('t') {
+                return Err(invalid_line());
+            }
+        }
+
+        profile.stacks = stacks
+            .into_iter()
+            .map(|(addresses, (objects, bytes))| HeapStack {
+                addresses,
+                objects,
+                bytes,
+            })
+            .collect();
+        Ok(profile)
+    }
+
+    /// Returns `n` stacks with the most live bytes.
+    pub fn top(&self, n: usize) -> Vec<&HeapStack> {
+        let mut stacks = self.stacks.iter().collect::<Vec<_>>();
+        stacks.sort_by_key(|stack| std::cmp::Reverse(stack.bytes));
+        stacks.truncate(n);
+        stacks
+    }
+
+    /// Returns the growth since the `base` profile.
+    ///
+    /// Stacks which didn't change are omitted, values of shrunk stacks are negative.
+    pub fn diff(&self, base: &HeapProfile) -> HeapProfile {
+        let mut stacks = BTreeMap::<&[u64], (i64, i64)>::new();
+        for stack in &self.stacks {
+            let entry = stacks.entry(&stack.addresses).or_default();
+            entry.0 += stack.objects;
+            entry.1 += stack.bytes;
+        }
+        for stack in &base.stacks {
+            let entry = stacks.entry(&stack.addresses).or_default();
+            entry.0 -= stack.objects;
+            entry.1 -= stack.bytes;
+        }
+
+        HeapProfile {
+            sample_period: self.sample_period,
+            objects: self.objects - base.objects,
+            bytes: self.bytes - base.bytes,
+            stacks: stacks
+                .into_iter()
+                .filter(|(_, (objects, bytes))| *objects != 0 || *bytes != 0)
+                .map(|(addresses, (objects, bytes))| HeapStack {
+                    addresses: addresses.to_vec(),
+                    objects,
+                    bytes,
+                })
+                .collect(),
+        }
+    }
+
+    /// Resolves all addresses using the debug info of the current process.
+    ///
+    /// Only works for dumps made by the current process.
+    pub fn symbolize(&self) -> Symbols {
+        let addresses = self
+            .stacks
+            .iter()
+            .flat_map(|stack| stack.addresses.iter().copied())
+            .collect::<HashSet<_>>();
+        Symbols::resolve(addresses)
+    }
+
+    /// Writes stacks in the collapsed format (`root;...;leaf bytes`) used by flamegraph tools.
+    ///
+    /// Stacks with non-positive sizes are skipped.
+    pub fn write_collapsed<W: Write>(
+        &self,
+        symbols: &Symbols,
+        mut writer: W,
+    ) -> std::io::Result<()> {
+        for stack in self.stacks.iter().filter(|stack| stack.bytes > 0) {
+            let mut frames = Vec::new();
+            for &address in stack.addresses.iter().rev() {
+                frames.extend(
+                    symbols
+                        .frames(address)
+                        .iter()
+                        .rev()
+                        .map(|frame| frame.name.clone()),
+                );
+            }
+            writeln!(writer, "{} {}", frames.join(";"), stack.bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Encodes the profile in the pprof protobuf format (uncompressed).
+    pub fn to_pprof(&self, symbols: &Symbols) -> Vec<u8> {
+        pprof::encode(self, symbols)
+    }
+}
+
+/// Symbolized frames of the return addresses.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    frames: HashMap<u64, Vec<Frame>>,
+}
+
+impl Symbols {
+    /// Resolves addresses using the debug info of the current process.
+    pub fn resolve<I: IntoIterator<Item = u64>>(addresses: I) -> Self {
+        let mut frames = HashMap::new();
+        for address in addresses {
+            let mut resolved = Vec::new();
+            // NOTE: `resolve` treats the address as a return address
+            // and looks up the preceding call instruction
+            backtrace::resolve(address as usize as *mut _, |symbol| {
+                if let Some(name) = symbol.name() {
+                    resolved.push(Frame {
+                        name: name.to_string(),
+                        file: symbol.filename().map(|file| file.display().to_string()),
+                        line: symbol.lineno(),
+                    });
+                }
+            });
+            if !resolved.is_empty() {
+                frames.insert(address, resolved);
+            }
+        }
+        Self { frames }
+    }
+
+    /// Inserts frames of the address, starting from the innermost inlined one.
+    pub fn insert(&mut self, address: u64, frames: Vec<Frame>) {
+        self.frames.insert(address, frames);
+    }
+
+    /// Returns frames of the address, starting from the innermost inlined one.
+    ///
+    /// Unresolved addresses are represented as a single frame with a hex name.
+    pub fn frames(&self, address: u64) -> std::borrow::Cow<'_, [Frame]> {
+        match self.frames.get(&address) {
+            Some(frames) => std::borrow::Cow::Borrowed(frames),
+            None => std::borrow::Cow::Owned(vec![Frame {
+                name: format!("{address:#x}"),
+                file: None,
+                line: None,
+            }]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub name: String,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum HeapProfileError {
+    #[error("failed to read the heap profile")]
+    Io(#[source] std::io::Error),
+    #[error("invalid or unsupported heap profile header")]
+    InvalidHeader,
+    #[error("invalid heap profile line {0}")]
+    InvalidLine(usize),
+}
+
+fn parse_address(address: &str) -> Option<u64> {
+    let address = address.strip_prefix("0x").unwrap_or(address);
+    u64::from_str_radix(address, 16).ok()
+}
+
+/// Parses `<objects>: <bytes> [<cum_objects>: <cum_bytes>]`.
+fn parse_counts(counts: &str) -> Option<(u64, u64)> {
+    let (current, _) = counts.split_once('[')?;
+    let (objects, bytes) = current.split_once(':')?;
+    Some((objects.trim().parse().ok()?, bytes.trim().parse().ok()?))
+}
+
+/// Estimates actual values from sampled ones (same as `jeprof`).
+fn unsample(objects: u64, bytes: u64, sample_period: u64) -> (i64, i64) {
+    if objects == 0 || bytes == 0 || sample_period == 0 {
+        return (objects as i64, bytes as i64);
+    }
+    let ratio = (bytes as f64 / objects as f64) / sample_period as f64;
+    let scale = 1.0 / (1.0 - (-ratio).exp());
+    (
+        (objects as f64 * scale).round() as i64,
+        (bytes as f64 * scale).round() as i64,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BASE: &str = "heap_v2/1
+  t*: 3: 3072 [0: 0]
+  t0: 3: 3072 [0: 0]
+@ 0x10 0x20 0x30
+  t*: 1: 1024 [0: 0]
+  t0: 1: 1024 [0: 0]
+@ 0x11 0x20 0x30
+  t*: 2: 2048 [0: 0]
+
+MAPPED_LIBRARIES:
+55d0c0000000-55d0c0001000 r--p 00000000 00:00 0 /bin/test
+";
+
+    const NEXT: &str = "heap_v2/1
+  t*: 5: 5120 [0: 0]
+@ 0x10 0x20 0x30
+  t*: 4: 4096 [0: 0]
+@ 0x11 0x20 0x30
+  t*: 1: 1024 [0: 0]
+";
+
+    fn symbols() -> Symbols {
+        let mut symbols = Symbols::default();
+        for (address, name) in [(0x10, "alloc_a"), (0x20, "caller"), (0x30, "main")] {
+            symbols.insert(
+                address,
+                vec![Frame {
+                    name: name.to_owned(),
+                    file: None,
+                    line: None,
+                }],
+            );
+        }
+        symbols
+    }
+
+    #[test]
+    fn parse_and_diff() {
+        let base = HeapProfile::parse(BASE).unwrap();
+        assert_eq!(base.sample_period, 1);
+        assert_eq!((base.objects, base.bytes), (3, 3072));
+        assert_eq!(base.stacks.len(), 2);
+        assert_eq!(base.top(1)[0].addresses, [0x11, 0x20, 0x30]);
+
+        let next = HeapProfile::parse(NEXT).unwrap();
+        let diff = next.diff(&base);
+        assert_eq!(diff.bytes, 2048);
+        assert_eq!(
+            diff.stacks
+                .iter()
+                .map(|stack| stack.bytes)
+                .collect::<Vec<_>>(),
+            [3072, -1024]
+        );
+
+        let mut collapsed = Vec::new();
+        diff.write_collapsed(&symbols(), &mut collapsed).unwrap();
+        assert_eq!(
+            String::from_utf8(collapsed).unwrap(),
+            "main;caller;alloc_a 3072\n"
+        );
+
+        let mut collapsed = Vec::new();
+        base.write_collapsed(&symbols(), &mut collapsed).unwrap();
+        assert_eq!(
+            String::from_utf8(collapsed).unwrap(),
+            "main;caller;alloc_a 1024\nmain;caller;0x11 2048\n"
+        );
+
+        assert!(!next.to_pprof(&symbols()).is_empty());
+    }
+
+    #[test]
+    fn unsample_values() {
+        let profile = HeapProfile::parse("heap_v2/524288\n  t*: 1: 128 [0: 0]\n").unwrap();
+        assert_eq!(profile.objects, 4097);
+        assert_eq!(profile.bytes, 524352);
+
+        assert!(matches!(
+            HeapProfile::parse("heap/1\n"),
+            Err(HeapProfileError::InvalidHeader)
+        ));
+        assert!(matches!(
+            HeapProfile::parse("heap_v2/1\n@ 0xzz\n"),
+            Err(HeapProfileError::InvalidLine(2))
+        ));
+    }
+
+    #[test]
+    fn symbolize_own_address() {
+        let address = symbolize_own_address as fn() as usize as u64 + 1;
+        let symbols = Symbols::resolve([address]);
+        assert!(symbols
+            .frames(address)
+            .iter()
+            .any(|frame| frame.name.contains("symbolize_own_address")));
+    }
+}
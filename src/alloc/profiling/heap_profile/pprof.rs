@@ -0,0 +1,163 @@
+//! Minimal encoder of the pprof `Profile` message
+//! (see `profile.proto` in the pprof repository).
+
+use std::collections::HashMap;
+
+use super::{HeapProfile, Symbols};
+
+pub fn encode(profile: &HeapProfile, symbols: &Symbols) -> Vec<u8> {
+    let mut strings = StringTable::default();
+    let mut functions = HashMap::<(String, Option<String>), u64>::new();
+    let mut locations = HashMap::<u64, u64>::new();
+
+    let mut functions_buf = Vec::new();
+    let mut locations_buf = Vec::new();
+    let mut samples_buf = Vec::new();
+
+    for stack in &profile.stacks {
+        let mut location_ids = Vec::with_capacity(stack.addresses.len());
+        for &address in &stack.addresses {
+            let next_id = locations.len() as u64 + 1;
+            let location_id = *locations.entry(address).or_insert_with(|| {
+                let mut location = Vec::new();
+                write_varint_field(&mut location, 1, next_id);
+                write_varint_field(&mut location, 3, address);
+
+                for frame in symbols.frames(address).iter() {
+                    let next_id = functions.len() as u64 + 1;
+                    let function_id = *functions
+                        .entry((frame.name.clone(), frame.file.clone()))
+                        .or_insert_with(|| {
+                            let name = strings.get(&frame.name);
+                            let file = strings.get(frame.file.as_deref().unwrap_or_default());
+
+                            let mut function = Vec::new();
+                            write_varint_field(&mut function, 1, next_id);
+                            write_varint_field(&mut function, 2, name);
+                            write_varint_field(&mut function, 3, name);
+                            write_varint_field(&mut function, 4, file);
+                            write_message_field(&mut functions_buf, 5, &function);
+                            next_id
+                        });
+
+                    let mut line = Vec::new();
+                    write_varint_field(&mut line, 1, function_id);
+                    write_varint_field(&mut line, 2, frame.line.unwrap_or_default() as u64);
+                    write_message_field(&mut location, 4, &line);
+                }
+
+                write_message_field(&mut locations_buf, 4, &location);
+                next_id
+            });
+            location_ids.push(location_id);
+        }
+
+        let mut sample = Vec::new();
+        write_packed_field(&mut sample, 1, location_ids.iter().copied());
+        write_packed_field(
+            &mut sample,
+            2,
+            [stack.objects as u64, stack.bytes as u64].into_iter(),
+        );
+        write_message_field(&mut samples_buf, 2, &sample);
+    }
+
+    let mut output = Vec::new();
+    for (ty, unit) in [("objects", "count"), ("space", "bytes")] {
+        let value_type = value_type(&mut strings, ty, unit);
+        write_message_field(&mut output, 1, &value_type);
+    }
+    output.extend_from_slice(&samples_buf);
+    output.extend_from_slice(&locations_buf);
+    output.extend_from_slice(&functions_buf);
+
+    let period_type = value_type(&mut strings, "space", "bytes");
+    for string in &strings.items {
+        write_bytes_field(&mut output, 6, string.as_bytes());
+    }
+    write_message_field(&mut output, 11, &period_type);
+    write_varint_field(&mut output, 12, profile.sample_period);
+    output
+}
+
+fn value_type(strings: &mut StringTable, ty: &str, unit: &str) -> Vec<u8> {
+    let mut value_type = Vec::new();
+    write_varint_field(&mut value_type, 1, strings.get(ty));
+    write_varint_field(&mut value_type, 2, strings.get(unit));
+    value_type
+}
+
+struct StringTable {
+    items: Vec<String>,
+    indices: HashMap<String, u64>,
+}
+
+impl Default for StringTable {
+    fn default() -> Self {
+        // NOTE: the first string must always be empty
+        Self {
+            items: vec![String::new()],
+            indices: HashMap::from([(String::new(), 0)]),
+        }
+    }
+}
+
+impl StringTable {
+    fn get(&mut self, string: &str) -> u64 {
+        if let Some(index) = self.indices.get(string) {
+            return *index;
+        }
+        let index = self.items.len() as u64;
+        self.items.push(string.to_owned());
+        self.indices.insert(string.to_owned(), index);
+        index
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_varint_field(buf: &mut Vec<u8>, field: u32, value: u64) {
+    write_varint(buf, (field as u64) << 3);
+    write_varint(buf, value);
+}
+
+fn write_bytes_field(buf: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    write_varint(buf, ((field as u64) << 3) | 2);
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_message_field(buf: &mut Vec<u8>, field: u32, message: &[u8]) {
+    write_bytes_field(buf, field, message);
+}
+
+fn write_packed_field<I: Iterator<Item = u64>>(buf: &mut Vec<u8>, field: u32, values: I) {
+    let mut packed = Vec::new();
+    for value in values {
+        write_varint(&mut packed, value);
+    }
+    write_bytes_field(buf, field, &packed);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn varint_encoding() {
+        let mut buf = Vec::new();
+        write_varint(&mut buf, 1);
+        write_varint(&mut buf, 300);
+        write_varint(&mut buf, -1i64 as u64);
+        assert_eq!(
+            buf,
+            [1, 0xac, 0x02, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use std::future::Future;
+use std::time::Duration;
+
+use super::{fetch_detailed_stats, fetch_stats, JemallocStats};
+use crate::alloc::task::{declare_task_handle, TaskHandle};
+use metrics::{Gauge, Label};
+
+/// Periodic exporter of the allocator stats.
+///
+/// Exports the following metrics (names start with the configured prefix):
+/// - `<prefix>_{allocated,active,metadata,resident,mapped,retained,dirty,fragmentation}_bytes`;
+/// - `<prefix>_fragmentation_percent` — share of active memory which is not allocated;
+/// - `<prefix>_resident_to_allocated_ratio`;
+/// - `<prefix>_stats_errors_total` — number of failed stats fetches;
+/// - per arena and per size class stats if `detailed` is enabled.
+#[derive(Debug, Clone)]
+pub struct AllocatorMetrics {
+    interval: Duration,
+    prefix: String,
+    labels: Vec<Label>,
+    error_backoff: Duration,
+    max_error_backoff: Duration,
+    detailed: bool,
+}
+
+impl Default for AllocatorMetrics {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            prefix: "jemalloc".to_owned(),
+            labels: Vec::new(),
+            error_backoff: Duration::from_secs(5),
+            max_error_backoff: Duration::from_secs(300),
+            detailed: true,
+        }
+    }
+}
+
+impl AllocatorMetrics {
+    /// Interval between exports. Default: 5s.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Prefix of all metric names. Default: `jemalloc`.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Adds a label to all exported metrics.
+    pub fn with_label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.push(Label::new(key.into(), value.into()));
+        self
+    }
+
+    /// Delay after a failed fetch which is doubled on each consecutive
+    /// failure up to `max`. Default: 5s up to 5m.
+    pub fn with_error_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.error_backoff = initial;
+        self.max_error_backoff = max.max(initial);
+        self
+    }
+
+    /// Whether to export per arena and per size class stats. Default: `true`.
+    pub fn with_detailed_stats(mut self, detailed: bool) -> Self {
+        self.detailed = detailed;
+        self
+    }
+
+    /// Spawns the export loop on the current tokio runtime.
+    pub fn spawn(self) -> AllocatorMetricsHandle {
+        AllocatorMetricsHandle(TaskHandle::spawn(|stop_rx| {
+            self.run_until(async move {
+                stop_rx.await.ok();
+            })
+        }))
+    }
+
+    /// Exports stats forever.
+    pub async fn run(self) {
+        self.run_until(std::future::pending::<()>()).await
+    }
+
+    /// Exports stats until the `stop` future completes.
+    pub async fn run_until<F: Future>(self, stop: F) {
+        tokio::pin!(stop);
+
+        let gauges = StatsGauges::new(&self.prefix, &self.labels);
+        let errors = metrics::counter!(
+            format!("{}_stats_errors_total", self.prefix),
+            self.labels.clone()
+        );
+
+        let mut backoff = self.error_backoff;
+        loop {
+            let delay = match fetch_stats() {
+                Ok(stats) => {
+                    gauges.set(&stats);
+                    if self.detailed {
+                        match fetch_detailed_stats() {
+                            Ok(detailed) => detailed.export_metrics(&self.prefix, &self.labels),
+                            Err(e) => {
+                                errors.increment(1);
+                                log::warn!("failed to fetch detailed allocator stats: {e:?}");
+                            }
+                        }
+                    }
+                    backoff = self.error_backoff;
+                    self.interval
+                }
+                Err(e) => {
+                    errors.increment(1);
+                    log::warn!("failed to fetch allocator stats, retrying in {backoff:?}: {e:?}");
+                    let delay = backoff;
+                    backoff = (backoff * 2).min(self.max_error_backoff);
+                    delay
+                }
+            };
+
+            tokio::select! {
+                _ = &mut stop => break,
+                _ = tokio::time::sleep(delay) => {}
+            }
+        }
+    }
+}
+
+declare_task_handle! {
+    /// Handle of the spawned [`AllocatorMetrics`] loop.
+    AllocatorMetricsHandle
+}
+
+struct StatsGauges {
+    allocated: Gauge,
+    active: Gauge,
+    metadata: Gauge,
+    resident: Gauge,
+    mapped: Gauge,
+    retained: Gauge,
+    dirty: Gauge,
+    fragmentation: Gauge,
+    fragmentation_percent: Gauge,
+    resident_to_allocated_ratio: Gauge,
+}
+
+impl StatsGauges {
+    fn new(prefix: &str, labels: &[Label]) -> Self {
+        let gauge = |name: &str| metrics::gauge!(format!("{prefix}_{name}"), labels.to_vec());
+        Self {
+            allocated: gauge("allocated_bytes"),
+            active: gauge("active_bytes"),
+            metadata: gauge("metadata_bytes"),
+            resident: gauge("resident_bytes"),
+            mapped: gauge("mapped_bytes"),
+            retained: gauge("retained_bytes"),
+            dirty: gauge("dirty_bytes"),
+            fragmentation: gauge("fragmentation_bytes"),
+            fragmentation_percent: gauge("fragmentation_percent"),
+            resident_to_allocated_ratio: gauge("resident_to_allocated_ratio"),
+        }
+    }
+
+    fn set(&self, stats: &JemallocStats) {
+        self.allocated.set(stats.allocated as f64);
+        self.active.set(stats.active as f64);
+        self.metadata.set(stats.metadata as f64);
+        self.resident.set(stats.resident as f64);
+        self.mapped.set(stats.mapped as f64);
+        self.retained.set(stats.retained as f64);
+        self.dirty.set(stats.dirty as f64);
+        self.fragmentation.set(stats.fragmentation as f64);
+        self.fragmentation_percent
+            .set(stats.fragmentation_percent());
+        self.resident_to_allocated_ratio
+            .set(stats.resident_to_allocated_ratio());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn stop_loop() {
+        let handle = AllocatorMetrics::default()
+            .with_interval(Duration::from_millis(10))
+            .with_prefix("test")
+            .with_label("service", "test")
+            .spawn();
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(!handle.is_finished());
+        handle.stop().await;
+    }
+
+    #[test]
+    fn derived_ratios() {
+        let stats = JemallocStats {
+            allocated: 750,
+            active: 1000,
+            metadata: 0,
+            resident: 1500,
+            mapped: 2000,
+            retained: 0,
+            dirty: 500,
+            fragmentation: 250,
+        };
+        assert_eq!(stats.fragmentation_percent(), 25.0);
+        assert_eq!(stats.resident_to_allocated_ratio(), 2.0);
+    }
+}
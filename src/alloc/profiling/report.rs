@@ -0,0 +1,273 @@
+use std::fmt;
+use std::time::Duration;
+#[cfg(feature = "alloc-tasks")]
+use std::time::Instant;
+
+use super::JemallocStats;
+#[cfg(feature = "alloc-tasks")]
+use crate::alloc::task::{declare_task_handle, TaskHandle};
+
+macro_rules! stats_fields {
+    ($macro:ident) => {
+        $macro!(
+            allocated,
+            active,
+            metadata,
+            resident,
+            mapped,
+            retained,
+            dirty,
+            fragmentation
+        )
+    };
+}
+
+/// Difference between two [`JemallocStats`] snapshots.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JemallocStatsDelta {
+    pub allocated: i64,
+    pub active: i64,
+    pub metadata: i64,
+    pub resident: i64,
+    pub mapped: i64,
+    pub retained: i64,
+    pub dirty: i64,
+    pub fragmentation: i64,
+}
+
+/// Change of [`JemallocStats`] in bytes per second.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JemallocStatsRates {
+    pub allocated: f64,
+    pub active: f64,
+    pub metadata: f64,
+    pub resident: f64,
+    pub mapped: f64,
+    pub retained: f64,
+    pub dirty: f64,
+    pub fragmentation: f64,
+}
+
+impl JemallocStats {
+    /// Returns the difference since the `prev` snapshot.
+    pub fn delta(&self, prev: &JemallocStats) -> JemallocStatsDelta {
+        macro_rules! delta {
+            ($($field:ident),*) => {
+                JemallocStatsDelta {
+                    $($field: self.$field as i64 - prev.$field as i64),*
+                }
+            };
+        }
+        stats_fields!(delta)
+    }
+
+    /// Returns the change per second since the `prev` snapshot made `elapsed` ago.
+    pub fn rates(&self, prev: &JemallocStats, elapsed: Duration) -> JemallocStatsRates {
+        self.delta(prev).per_second(elapsed)
+    }
+}
+
+impl std::ops::Sub for JemallocStats {
+    type Output = JemallocStatsDelta;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.delta(&rhs)
+    }
+}
+
+impl JemallocStatsDelta {
+    /// Divides the delta by the elapsed time.
+    pub fn per_second(&self, elapsed: Duration) -> JemallocStatsRates {
+        let secs = elapsed.as_secs_f64();
+        macro_rules! rates {
+            ($($field:ident),*) => {
+                JemallocStatsRates {
+                    $($field: if secs > 0.0 { self.$field as f64 / secs } else { 0.0 }),*
+                }
+            };
+        }
+        stats_fields!(rates)
+    }
+}
+
+impl fmt::Display for JemallocStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        MemoryReport {
+            stats: *self,
+            rates: None,
+        }
+        .fmt(f)
+    }
+}
+
+/// Human-readable stats with optional growth rates
+/// (e.g. `1.2 GiB allocated (+35 MiB/min), ...`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryReport {
+    pub stats: JemallocStats,
+    pub rates: Option<JemallocStatsRates>,
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        type Rate = fn(&JemallocStatsRates) -> f64;
+        let fields: [(&str, u64, Rate); 7] = [
+            ("allocated", self.stats.allocated, |r| r.allocated),
+            ("active", self.stats.active, |r| r.active),
+            ("resident", self.stats.resident, |r| r.resident),
+            ("mapped", self.stats.mapped, |r| r.mapped),
+            ("retained", self.stats.retained, |r| r.retained),
+            ("metadata", self.stats.metadata, |r| r.metadata),
+            ("dirty", self.stats.dirty, |r| r.dirty),
+        ];
+
+        for (i, (name, value, rate)) in fields.into_iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{} {name}", ByteSize(value as f64))?;
+            if let Some(rate) = self.rates.as_ref().map(rate) {
+                let sign = if rate < 0.0 { '-' } else { '+' };
+                write!(f, " ({sign}{}/min)", ByteSize(rate.abs() * 60.0))?;
+            }
+        }
+        write!(
+            f,
+            ", {:.1}% fragmentation",
+            self.stats.fragmentation_percent()
+        )
+    }
+}
+
+/// Formats bytes with binary units (e.g. `1.2 GiB`, `35 MiB`).
+struct ByteSize(f64);
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+
+        let mut value = self.0;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+
+        if unit == 0 || value >= 10.0 {
+            write!(f, "{value:.0} {}", UNITS[unit])
+        } else {
+            write!(f, "{value:.1} {}", UNITS[unit])
+        }
+    }
+}
+
+/// Background task which periodically logs a [`MemoryReport`].
+#[cfg(feature = "alloc-tasks")]
+#[derive(Debug, Clone)]
+pub struct MemoryReporter {
+    interval: Duration,
+    target: String,
+}
+
+#[cfg(feature = "alloc-tasks")]
+impl Default for MemoryReporter {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            target: module_path!().to_owned(),
+        }
+    }
+}
+
+#[cfg(feature = "alloc-tasks")]
+impl MemoryReporter {
+    /// Interval between reports. Default: 1m.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Log target of reports. Default: the module path.
+    pub fn with_target(mut self, target: impl Into<String>) -> Self {
+        self.target = target.into();
+        self
+    }
+
+    /// Spawns the reporter on the current tokio runtime.
+    pub fn spawn(self) -> MemoryReporterHandle {
+        MemoryReporterHandle(TaskHandle::spawn(|mut stop_rx| async move {
+            let mut prev = None::<(JemallocStats, Instant)>;
+            loop {
+                match super::fetch_stats() {
+                    Ok(stats) => {
+                        let now = Instant::now();
+                        let report = MemoryReport {
+                            stats,
+                            rates: prev.map(|(prev, at)| stats.rates(&prev, now - at)),
+                        };
+                        log::info!(target: &self.target, "memory report: {report}");
+                        prev = Some((stats, now));
+                    }
+                    Err(e) => log::warn!("failed to fetch allocator stats: {e:?}"),
+                }
+
+                tokio::select! {
+                    _ = &mut stop_rx => break,
+                    _ = tokio::time::sleep(self.interval) => {}
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(feature = "alloc-tasks")]
+declare_task_handle! {
+    /// Handle of the spawned [`MemoryReporter`].
+    MemoryReporterHandle
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stats(allocated: u64) -> JemallocStats {
+        JemallocStats {
+            allocated,
+            active: allocated + (allocated >> 2),
+            metadata: 1 << 20,
+            resident: allocated * 2,
+            mapped: allocated * 2,
+            retained: 0,
+            dirty: 512,
+            fragmentation: allocated >> 2,
+        }
+    }
+
+    #[test]
+    fn diff_and_format() {
+        let prev = stats(1 << 30);
+        let next = stats((1 << 30) + (35 << 20));
+
+        let delta = next - prev;
+        assert_eq!(delta.allocated, 35 << 20);
+        assert_eq!(prev.delta(&next).allocated, -(35 << 20));
+
+        let rates = next.rates(&prev, Duration::from_secs(60));
+        assert_eq!(rates.allocated * 60.0, (35 << 20) as f64);
+
+        let report = MemoryReport {
+            stats: next,
+            rates: Some(rates),
+        }
+        .to_string();
+        assert!(report.starts_with("1.0 GiB allocated (+35 MiB/min), "));
+        assert!(report.contains("512 B dirty (+0 B/min)"));
+        assert!(report.ends_with("20.0% fragmentation"));
+
+        assert_eq!(ByteSize(0.0).to_string(), "0 B");
+        assert_eq!(ByteSize(1.2 * (1u64 << 30) as f64).to_string(), "1.2 GiB");
+        assert_eq!(ByteSize(1536.0).to_string(), "1.5 KiB");
+    }
+}
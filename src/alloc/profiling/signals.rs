@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+
+use tokio::signal::unix::{signal, SignalKind};
+
+use super::{start, stop, ProfilingSupervisor};
+use crate::alloc::params;
+use crate::alloc::task::{declare_task_handle, TaskHandle};
+
+/// Background task which toggles profiling on `SIGUSR1`
+/// and dumps the heap profile on `SIGUSR2`.
+///
+/// Dumps are named and rotated the same way as in [`ProfilingSupervisor`].
+#[derive(Debug, Clone)]
+pub struct ProfilingSignals {
+    dumps: ProfilingSupervisor,
+}
+
+impl ProfilingSignals {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        Self {
+            dumps: ProfilingSupervisor::new(directory),
+        }
+    }
+
+    /// Prefix of the dump file names. Default: `heap`.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.dumps = self.dumps.with_prefix(prefix);
+        self
+    }
+
+    /// Number of the most recent dumps to keep. Default: 10.
+    pub fn with_max_dumps(mut self, max_dumps: usize) -> Self {
+        self.dumps = self.dumps.with_max_dumps(max_dumps);
+        self
+    }
+
+    /// Subscribes to signals and spawns the task on the current tokio runtime.
+    pub fn spawn(self) -> std::io::Result<ProfilingSignalsHandle> {
+        let mut toggle = signal(SignalKind::user_defined1())?;
+        let mut dump = signal(SignalKind::user_defined2())?;
+
+        let task = TaskHandle::spawn(|mut stop_rx| async move {
+            loop {
+                tokio::select! {
+                    _ = &mut stop_rx => break,
+                    Some(_) = toggle.recv() => toggle_profiling(),
+                    Some(_) = dump.recv() => {
+                        let dumps = self.dumps.clone();
+                        match tokio::task::spawn_blocking(move || dumps.dump_now()).await {
+                            Ok(Ok(path)) => log::info!("heap profile dumped on SIGUSR2 to {path:?}"),
+                            Ok(Err(e)) => log::error!("failed to dump the heap profile: {e}"),
+                            Err(e) => log::error!("profiling dump task failed: {e}"),
+                        }
+                    }
+                    else => break,
+                }
+            }
+        });
+
+        Ok(ProfilingSignalsHandle(task))
+    }
+}
+
+declare_task_handle! {
+    /// Handle of the spawned [`ProfilingSignals`] task.
+    ProfilingSignalsHandle
+}
+
+fn toggle_profiling() {
+    match params::prof_active() {
+        Ok(true) => {
+            if let Err(e) = stop() {
+                log::error!("failed to stop profiling on SIGUSR1: {e:?}");
+            }
+        }
+        Ok(false) => {
+            if let Err(e) = start() {
+                log::error!("failed to start profiling on SIGUSR1: {e}");
+            }
+        }
+        Err(e) => log::error!("failed to get profiling state: {e}"),
+    }
+}
+
+// run with `MALLOC_CONF="prof:true" cargo test --ignored`
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    #[ignore]
+    async fn dump_on_signal() {
+        let dir = tempfile::tempdir().unwrap();
+        let handle = ProfilingSignals::new(dir.path()).spawn().unwrap();
+
+        let active = params::prof_active().unwrap();
+        unsafe { libc::raise(libc::SIGUSR1) };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(params::prof_active().unwrap(), !active);
+
+        unsafe { libc::raise(libc::SIGUSR2) };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        handle.stop().await;
+
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,206 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+#[cfg(feature = "alloc-tasks")]
+use std::time::Instant;
+
+use super::{dump, DumpError};
+#[cfg(feature = "alloc-tasks")]
+use crate::alloc::task::{declare_task_handle, TaskHandle};
+
+/// Background task which dumps heap profiles periodically and/or when
+/// the allocated memory grows by the specified amount since the last dump.
+///
+/// Dumps are named as `<prefix>.<unix_ms>.<pid>.heap` and only the last
+/// `max_dumps` of them are kept in the directory.
+#[derive(Debug, Clone)]
+pub struct ProfilingSupervisor {
+    directory: PathBuf,
+    prefix: String,
+    interval: Option<Duration>,
+    growth_threshold: Option<u64>,
+    check_interval: Duration,
+    max_dumps: usize,
+}
+
+impl ProfilingSupervisor {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        Self {
+            directory: directory.into(),
+            prefix: "heap".to_owned(),
+            interval: None,
+            growth_threshold: None,
+            check_interval: Duration::from_secs(1),
+            max_dumps: 10,
+        }
+    }
+
+    /// Prefix of the dump file names. Default: `heap`.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Dumps the profile each `interval`.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Dumps the profile each time the allocated memory grows by `bytes`
+    /// since the last dump.
+    pub fn with_growth_threshold(mut self, bytes: u64) -> Self {
+        self.growth_threshold = Some(bytes);
+        self
+    }
+
+    /// How often the allocated memory is checked. Default: 1s.
+    pub fn with_check_interval(mut self, interval: Duration) -> Self {
+        self.check_interval = interval;
+        self
+    }
+
+    /// Number of the most recent dumps to keep. Default: 10.
+    pub fn with_max_dumps(mut self, max_dumps: usize) -> Self {
+        self.max_dumps = max_dumps.max(1);
+        self
+    }
+
+    /// Dumps the profile into the directory and removes old dumps.
+    pub fn dump_now(&self) -> Result<PathBuf, DumpError> {
+        std::fs::create_dir_all(&self.directory).map_err(DumpError::Io)?;
+
+        let path = self.directory.join(format!(
+            "{}.{:013}.{}.heap",
+            self.prefix,
+            crate::now_ms_u64(),
+            std::process::id()
+        ));
+        dump(&path)?;
+
+        if let Err(e) = prune_dumps(&self.directory, &self.prefix, self.max_dumps) {
+            log::warn!("failed to remove old profiling dumps: {e}");
+        }
+        Ok(path)
+    }
+
+    /// Spawns the supervisor on the current tokio runtime.
+    #[cfg(feature = "alloc-tasks")]
+    pub fn spawn(self) -> ProfilingSupervisorHandle {
+        ProfilingSupervisorHandle(TaskHandle::spawn(|mut stop_rx| async move {
+            let mut last_dump = Instant::now();
+            let mut last_allocated = allocated();
+
+            loop {
+                tokio::select! {
+                    _ = &mut stop_rx => break,
+                    _ = tokio::time::sleep(self.check_interval) => {}
+                }
+
+                let allocated = allocated();
+                let by_interval =
+                    matches!(self.interval, Some(interval) if last_dump.elapsed() >= interval);
+                let by_growth = matches!(
+                    (self.growth_threshold, allocated, last_allocated),
+                    (Some(threshold), Some(allocated), Some(last))
+                        if allocated >= last.saturating_add(threshold)
+                );
+                if !by_interval && !by_growth {
+                    continue;
+                }
+
+                let this = self.clone();
+                match tokio::task::spawn_blocking(move || this.dump_now()).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => log::error!("failed to dump the heap profile: {e}"),
+                    Err(e) => log::error!("profiling dump task failed: {e}"),
+                }
+
+                last_dump = Instant::now();
+                if allocated.is_some() {
+                    last_allocated = allocated;
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(feature = "alloc-tasks")]
+declare_task_handle! {
+    /// Handle of the spawned [`ProfilingSupervisor`].
+    ProfilingSupervisorHandle
+}
+
+#[cfg(feature = "alloc-tasks")]
+fn allocated() -> Option<u64> {
+    match super::fetch_stats() {
+        Ok(stats) => Some(stats.allocated),
+        Err(e) => {
+            log::warn!("failed to fetch allocator stats: {e:?}");
+            None
+        }
+    }
+}
+
+/// Removes all but the `keep` most recent dumps with the specified prefix.
+fn prune_dumps(directory: &Path, prefix: &str, keep: usize) -> std::io::Result<()> {
+    let prefix = format!("{prefix}.");
+
+    let mut dumps = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        if name.starts_with(&prefix) && name.ends_with(".heap") {
+            dumps.push(entry.path());
+        }
+    }
+
+    // NOTE: timestamps are zero-padded so names are sorted chronologically
+    dumps.sort_unstable();
+    let outdated = dumps.len().saturating_sub(keep);
+    for path in &dumps[..outdated] {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prune_old_dumps() {
+        let dir = tempfile::tempdir().unwrap();
+        for (i, name) in [
+            "heap.0000000000003.1.heap",
+            "heap.0000000000001.2.heap",
+            "heap.0000000000002.1.heap",
+            "other.0000000000001.1.heap",
+            "heap.txt",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            std::fs::write(dir.path().join(name), i.to_string()).unwrap();
+        }
+
+        prune_dumps(dir.path(), "heap", 2).unwrap();
+
+        let mut names = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            [
+                "heap.0000000000002.1.heap",
+                "heap.0000000000003.1.heap",
+                "heap.txt",
+                "other.0000000000001.1.heap",
+            ]
+        );
+    }
+}
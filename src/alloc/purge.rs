@@ -0,0 +1,233 @@
+#[cfg(feature = "alloc-tasks")]
+use std::sync::Arc;
+#[cfg(feature = "alloc-tasks")]
+use std::time::Duration;
+
+use super::ctl::{call_dynamic, read_dynamic, write_dynamic};
+#[cfg(feature = "alloc-tasks")]
+use super::task::{declare_task_handle, TaskHandle};
+use super::{params, JemallocError, MALLCTL_ARENAS_ALL};
+
+/// Purges all unused dirty pages of the arena ([`MALLCTL_ARENAS_ALL`] for all arenas).
+pub fn purge_arena(arena: u32) -> Result<(), JemallocError> {
+    call_dynamic(format!("arena.{arena}.purge"))
+}
+
+/// Purges unused dirty pages of the arena according to its decay settings
+/// ([`MALLCTL_ARENAS_ALL`] for all arenas).
+pub fn decay_arena(arena: u32) -> Result<(), JemallocError> {
+    call_dynamic(format!("arena.{arena}.decay"))
+}
+
+/// Purges all arenas, measuring dirty memory before and after the purge.
+pub fn purge_all() -> Result<PurgeReport, JemallocError> {
+    let dirty_before = dirty_bytes()?;
+    purge_arena(MALLCTL_ARENAS_ALL)?;
+    let dirty_after = dirty_bytes()?;
+    Ok(PurgeReport {
+        dirty_before,
+        dirty_after,
+    })
+}
+
+/// Returns the size of dirty pages across all arenas in bytes.
+pub fn dirty_bytes() -> Result<u64, JemallocError> {
+    // Stats are cached. Need to advance epoch to refresh.
+    params::set_epoch(1)?;
+    let pdirty = read_dynamic::<usize>(format!("stats.arenas.{MALLCTL_ARENAS_ALL}.pdirty"))?;
+    Ok(pdirty as u64 * params::arenas_page()? as u64)
+}
+
+/// Returns the dirty decay time in ms of the arena (`-1` means purging is disabled).
+pub fn arena_dirty_decay_ms(arena: u32) -> Result<isize, JemallocError> {
+    read_dynamic(format!("arena.{arena}.dirty_decay_ms"))
+}
+
+/// Sets the dirty decay time in ms of the arena (`-1` disables purging).
+pub fn set_arena_dirty_decay_ms(arena: u32, ms: isize) -> Result<(), JemallocError> {
+    write_dynamic(format!("arena.{arena}.dirty_decay_ms"), ms)
+}
+
+/// Returns the muzzy decay time in ms of the arena (`-1` means purging is disabled).
+pub fn arena_muzzy_decay_ms(arena: u32) -> Result<isize, JemallocError> {
+    read_dynamic(format!("arena.{arena}.muzzy_decay_ms"))
+}
+
+/// Sets the muzzy decay time in ms of the arena (`-1` disables purging).
+pub fn set_arena_muzzy_decay_ms(arena: u32, ms: isize) -> Result<(), JemallocError> {
+    write_dynamic(format!("arena.{arena}.muzzy_decay_ms"), ms)
+}
+
+/// Sets the dirty decay time in ms for all existing and new arenas.
+pub fn set_dirty_decay_ms(ms: isize) -> Result<(), JemallocError> {
+    params::set_arenas_dirty_decay_ms(ms)?;
+    for_each_arena(|arena| set_arena_dirty_decay_ms(arena, ms))
+}
+
+/// Sets the muzzy decay time in ms for all existing and new arenas.
+pub fn set_muzzy_decay_ms(ms: isize) -> Result<(), JemallocError> {
+    params::set_arenas_muzzy_decay_ms(ms)?;
+    for_each_arena(|arena| set_arena_muzzy_decay_ms(arena, ms))
+}
+
+fn for_each_arena<F>(mut f: F) -> Result<(), JemallocError>
+where
+    F: FnMut(u32) -> Result<(), JemallocError>,
+{
+    for arena in 0..params::arenas_narenas()? {
+        if read_dynamic::<bool>(format!("arena.{arena}.initialized"))? {
+            f(arena)?;
+        }
+    }
+    Ok(())
+}
+
+/// Dirty memory before and after the purge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PurgeReport {
+    pub dirty_before: u64,
+    pub dirty_after: u64,
+}
+
+impl PurgeReport {
+    /// Number of released bytes.
+    pub fn released(&self) -> u64 {
+        self.dirty_before.saturating_sub(self.dirty_after)
+    }
+}
+
+#[cfg(feature = "alloc-tasks")]
+type PurgeCallback = Arc<dyn Fn(&PurgeReport) + Send + Sync>;
+
+/// Background task which purges all arenas when dirty memory exceeds the threshold.
+#[cfg(feature = "alloc-tasks")]
+#[derive(Clone)]
+pub struct AutoPurge {
+    threshold: u64,
+    interval: Duration,
+    on_purge: Option<PurgeCallback>,
+}
+
+#[cfg(feature = "alloc-tasks")]
+impl AutoPurge {
+    /// Creates a task which purges when there are more than `threshold` dirty bytes.
+    pub fn new(threshold: u64) -> Self {
+        Self {
+            threshold,
+            interval: Duration::from_secs(10),
+            on_purge: None,
+        }
+    }
+
+    /// Interval between checks. Default: 10s.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Callback which is called after each purge.
+    pub fn on_purge<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&PurgeReport) + Send + Sync + 'static,
+    {
+        self.on_purge = Some(Arc::new(f));
+        self
+    }
+
+    /// Spawns the task on the current tokio runtime.
+    pub fn spawn(self) -> AutoPurgeHandle {
+        AutoPurgeHandle(TaskHandle::spawn(|mut stop_rx| async move {
+            loop {
+                tokio::select! {
+                    _ = &mut stop_rx => break,
+                    _ = tokio::time::sleep(self.interval) => {}
+                }
+
+                let threshold = self.threshold;
+                let res = tokio::task::spawn_blocking(move || match dirty_bytes()? {
+                    dirty if dirty > threshold => purge_all().map(Some),
+                    _ => Ok(None),
+                })
+                .await;
+
+                match res {
+                    Ok(Ok(Some(report))) => {
+                        log::info!(
+                            "purged {} dirty bytes ({} before, {} after)",
+                            report.released(),
+                            report.dirty_before,
+                            report.dirty_after
+                        );
+                        if let Some(on_purge) = &self.on_purge {
+                            on_purge(&report);
+                        }
+                    }
+                    Ok(Ok(None)) => {}
+                    Ok(Err(e)) => log::error!("failed to purge arenas: {e}"),
+                    Err(e) => log::error!("purge task failed: {e}"),
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(feature = "alloc-tasks")]
+declare_task_handle! {
+    /// Handle of the spawned [`AutoPurge`] task.
+    AutoPurgeHandle
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decay_settings() {
+        let dirty = arena_dirty_decay_ms(0).unwrap();
+        set_arena_dirty_decay_ms(0, dirty + 1).unwrap();
+        assert_eq!(arena_dirty_decay_ms(0).unwrap(), dirty + 1);
+        set_arena_dirty_decay_ms(0, dirty).unwrap();
+
+        let muzzy = arena_muzzy_decay_ms(0).unwrap();
+        set_arena_muzzy_decay_ms(0, muzzy).unwrap();
+        assert_eq!(arena_muzzy_decay_ms(0).unwrap(), muzzy);
+
+        decay_arena(0).unwrap();
+        decay_arena(MALLCTL_ARENAS_ALL).unwrap();
+        assert!(matches!(
+            purge_arena(u32::MAX - 1),
+            Err(JemallocError::UnknownParam(_) | JemallocError::Other { .. })
+        ));
+    }
+
+    #[cfg(feature = "alloc-tasks")]
+    #[tokio::test]
+    async fn auto_purge() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let purged = Arc::new(AtomicBool::new(false));
+        let handle = AutoPurge::new(0)
+            .with_interval(Duration::from_millis(10))
+            .on_purge({
+                let purged = purged.clone();
+                move |_| purged.store(true, Ordering::Release)
+            })
+            .spawn();
+
+        for _ in 0..100 {
+            let data = vec![1u8; 16 << 20];
+            assert_eq!(data[data.len() - 1], 1);
+            drop(data);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            if purged.load(Ordering::Acquire) {
+                break;
+            }
+        }
+        handle.stop().await;
+
+        assert!(purged.load(Ordering::Acquire));
+        let report = purge_all().unwrap();
+        assert!(report.dirty_after <= report.dirty_before);
+    }
+}
@@ -0,0 +1,135 @@
+use std::ffi::{c_char, c_void, CStr};
+
+/// Sections of the `malloc_stats_print` output.
+///
+/// All sections are included by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct StatsPrintOptions {
+    /// General information that never changes during execution.
+    pub general: bool,
+    /// Merged arena stats.
+    pub merged_arenas: bool,
+    /// Destroyed merged arena stats.
+    pub destroyed_arenas: bool,
+    /// Per arena stats.
+    pub per_arena: bool,
+    /// Per size class stats for bins.
+    pub bins: bool,
+    /// Per size class stats for large objects.
+    pub large: bool,
+    /// Mutex stats.
+    pub mutex: bool,
+    /// Extent stats.
+    pub extents: bool,
+    /// HPA stats.
+    pub hpa: bool,
+}
+
+impl Default for StatsPrintOptions {
+    fn default() -> Self {
+        Self {
+            general: true,
+            merged_arenas: true,
+            destroyed_arenas: true,
+            per_arena: true,
+            bins: true,
+            large: true,
+            mutex: true,
+            extents: true,
+            hpa: true,
+        }
+    }
+}
+
+impl StatsPrintOptions {
+    /// Only merged arena totals without per arena and per size class stats.
+    pub fn summary() -> Self {
+        Self {
+            per_arena: false,
+            bins: false,
+            large: false,
+            mutex: false,
+            extents: false,
+            hpa: false,
+            ..Default::default()
+        }
+    }
+
+    fn to_opts(self, json: bool) -> Vec<u8> {
+        let mut opts = Vec::with_capacity(11);
+        if json {
+            opts.push(b'J');
+        }
+        for (enabled, flag) in [
+            (self.general, b'g'),
+            (self.merged_arenas, b'm'),
+            (self.destroyed_arenas, b'd'),
+            (self.per_arena, b'a'),
+            (self.bins, b'b'),
+            (self.large, b'l'),
+            (self.mutex, b'x'),
+            (self.extents, b'e'),
+            (self.hpa, b'h'),
+        ] {
+            if !enabled {
+                opts.push(flag);
+            }
+        }
+        opts.push(0);
+        opts
+    }
+}
+
+/// Captures the human-readable output of `malloc_stats_print`.
+pub fn stats_print(options: StatsPrintOptions) -> String {
+    String::from_utf8_lossy(&capture_stats(options, false)).into_owned()
+}
+
+/// Captures the output of `malloc_stats_print` in JSON format.
+///
+/// The output is returned as is and can be parsed with any JSON parser.
+pub fn stats_print_json(options: StatsPrintOptions) -> String {
+    String::from_utf8_lossy(&capture_stats(options, true)).into_owned()
+}
+
+fn capture_stats(options: StatsPrintOptions, json: bool) -> Vec<u8> {
+    unsafe extern "C" fn write_cb(opaque: *mut c_void, buf: *const c_char) {
+        // SAFETY: `opaque` is the buffer passed below and `buf` is a valid C string
+        let output = &mut *(opaque as *mut Vec<u8>);
+        output.extend_from_slice(CStr::from_ptr(buf).to_bytes());
+    }
+
+    let opts = options.to_opts(json);
+    let mut output = Vec::<u8>::with_capacity(64 << 10);
+    unsafe {
+        tikv_jemalloc_sys::malloc_stats_print(
+            Some(write_cb),
+            &mut output as *mut Vec<u8> as *mut c_void,
+            opts.as_ptr() as *const c_char,
+        )
+    };
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stats_as_json() {
+        let parse = |json: String| serde_json::from_str::<serde_json::Value>(&json).unwrap();
+
+        let stats = parse(stats_print_json(Default::default()));
+        assert!(stats["jemalloc"]["version"].is_string());
+        assert!(stats["jemalloc"]["stats"]["allocated"].as_u64().unwrap() > 0);
+        assert!(stats["jemalloc"]["stats.arenas"]["0"]["bins"].is_array());
+
+        let stats = parse(stats_print_json(StatsPrintOptions::summary()));
+        assert!(stats["jemalloc"]["stats.arenas"]["0"].is_null());
+
+        let stats = stats_print(StatsPrintOptions::summary());
+        assert!(stats.contains("Allocated:"));
+    }
+}
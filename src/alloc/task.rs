@@ -0,0 +1,64 @@
+use std::future::Future;
+
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// Spawned background task which is stopped when the handle is dropped.
+pub(crate) struct TaskHandle {
+    stop_tx: Option<oneshot::Sender<()>>,
+    task: JoinHandle<()>,
+}
+
+impl TaskHandle {
+    /// Spawns the task on the current tokio runtime.
+    ///
+    /// The receiver passed to `f` resolves when the task must stop.
+    pub(crate) fn spawn<F, Fut>(f: F) -> Self
+    where
+        F: FnOnce(oneshot::Receiver<()>) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (stop_tx, stop_rx) = oneshot::channel();
+        Self {
+            stop_tx: Some(stop_tx),
+            task: tokio::spawn(f(stop_rx)),
+        }
+    }
+
+    /// Stops the task and waits until it finishes.
+    pub(crate) async fn stop(mut self) {
+        if let Some(stop_tx) = self.stop_tx.take() {
+            stop_tx.send(()).ok();
+        }
+        (&mut self.task).await.ok();
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+/// Declares a public handle of the background task.
+macro_rules! declare_task_handle {
+    ($(#[doc = $doc:literal])* $name:ident) => {
+        $(#[doc = $doc])*
+        ///
+        /// The task is stopped when the handle is dropped.
+        #[must_use = "the task is stopped when the handle is dropped"]
+        pub struct $name($crate::alloc::task::TaskHandle);
+
+        impl $name {
+            /// Stops the task and waits until it finishes.
+            pub async fn stop(self) {
+                self.0.stop().await
+            }
+
+            /// Returns `true` if the task has finished.
+            pub fn is_finished(&self) -> bool {
+                self.0.is_finished()
+            }
+        }
+    };
+}
+
+pub(crate) use declare_task_handle;
@@ -0,0 +1,211 @@
+use std::cell::Cell;
+use std::ffi::CStr;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::ctl::read;
+use super::JemallocError;
+
+const THREAD_ALLOCATEDP: &[u8] = b"thread.allocatedp\0";
+const THREAD_DEALLOCATEDP: &[u8] = b"thread.deallocatedp\0";
+
+thread_local! {
+    static ALLOCATEDP: Cell<Option<*mut u64>> = const { Cell::new(None) };
+    static DEALLOCATEDP: Cell<Option<*mut u64>> = const { Cell::new(None) };
+}
+
+/// Total number of bytes allocated by the current thread.
+pub fn thread_allocated() -> Result<u64, JemallocError> {
+    read_counter(&ALLOCATEDP, THREAD_ALLOCATEDP)
+}
+
+/// Total number of bytes deallocated by the current thread.
+pub fn thread_deallocated() -> Result<u64, JemallocError> {
+    read_counter(&DEALLOCATEDP, THREAD_DEALLOCATEDP)
+}
+
+fn read_counter(
+    cache: &'static std::thread::LocalKey<Cell<Option<*mut u64>>>,
+    name: &'static [u8],
+) -> Result<u64, JemallocError> {
+    cache.with(|cache| {
+        let ptr = match cache.get() {
+            Some(ptr) => ptr,
+            None => {
+                let name = CStr::from_bytes_with_nul(name).expect("shouldn't fail");
+                let ptr = read::<*mut u64>(name)?;
+                cache.set(Some(ptr));
+                ptr
+            }
+        };
+        // SAFETY: the pointer refers to the counter of the current thread
+        // which lives as long as the thread itself
+        Ok(unsafe { ptr.read_volatile() })
+    })
+}
+
+/// Bytes allocated and deallocated during the measurement.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AllocationStats {
+    pub allocated: u64,
+    pub deallocated: u64,
+}
+
+impl AllocationStats {
+    /// Difference between allocated and deallocated bytes.
+    pub fn net(&self) -> i64 {
+        self.allocated as i64 - self.deallocated as i64
+    }
+
+    fn current() -> Result<Self, JemallocError> {
+        Ok(Self {
+            allocated: thread_allocated()?,
+            deallocated: thread_deallocated()?,
+        })
+    }
+
+    fn since(self, start: Self) -> Self {
+        Self {
+            allocated: self.allocated.wrapping_sub(start.allocated),
+            deallocated: self.deallocated.wrapping_sub(start.deallocated),
+        }
+    }
+}
+
+impl std::ops::AddAssign for AllocationStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.allocated += rhs.allocated;
+        self.deallocated += rhs.deallocated;
+    }
+}
+
+/// Measures allocations of the current thread since the scope was created.
+///
+/// Counters are thread-local so the scope can't be moved to another thread.
+/// Use [`measure_future_allocations`] for futures.
+pub struct AllocationScope {
+    start: AllocationStats,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl AllocationScope {
+    /// Fails if jemalloc doesn't provide thread counters.
+    pub fn new() -> Result<Self, JemallocError> {
+        Ok(Self {
+            start: AllocationStats::current()?,
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Returns allocations since the scope was created.
+    pub fn stats(&self) -> AllocationStats {
+        AllocationStats::current()
+            .expect("counters are cached by the scope")
+            .since(self.start)
+    }
+}
+
+/// Runs the closure and returns its result with the allocations made inside it.
+///
+/// The closure is executed even if allocations can't be measured.
+pub fn measure_allocations<F, R>(f: F) -> (R, Result<AllocationStats, JemallocError>)
+where
+    F: FnOnce() -> R,
+{
+    let scope = AllocationScope::new();
+    let result = f();
+    (result, scope.map(|scope| scope.stats()))
+}
+
+/// Wraps the future to measure allocations made while it is polled,
+/// regardless of the thread it is polled on.
+///
+/// The future is polled even if allocations can't be measured.
+pub fn measure_future_allocations<F: Future>(future: F) -> MeasureAllocations<F> {
+    MeasureAllocations {
+        future,
+        stats: Ok(AllocationStats::default()),
+    }
+}
+
+/// Future returned by [`measure_future_allocations`].
+pub struct MeasureAllocations<F> {
+    future: F,
+    stats: Result<AllocationStats, JemallocError>,
+}
+
+impl<F> MeasureAllocations<F> {
+    /// Returns allocations made so far.
+    pub fn stats(&self) -> Result<AllocationStats, &JemallocError> {
+        self.stats.as_ref().copied()
+    }
+}
+
+impl<F: Future> Future for MeasureAllocations<F> {
+    type Output = (F::Output, Result<AllocationStats, JemallocError>);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `future` is structurally pinned and is never moved
+        let this = unsafe { self.get_unchecked_mut() };
+
+        let start = AllocationStats::current();
+        let res = unsafe { Pin::new_unchecked(&mut this.future) }.poll(cx);
+        let delta = start.and_then(|start| Ok(AllocationStats::current()?.since(start)));
+
+        match (&mut this.stats, delta) {
+            (Ok(stats), Ok(delta)) => *stats += delta,
+            (stats @ Ok(_), Err(e)) => *stats = Err(e),
+            (Err(_), _) => {}
+        }
+
+        match res {
+            Poll::Ready(output) => {
+                let stats = std::mem::replace(&mut this.stats, Ok(AllocationStats::default()));
+                Poll::Ready((output, stats))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn measure_closure() {
+        let before = thread_allocated().unwrap();
+        let (len, stats) = measure_allocations(|| {
+            let data = vec![1u8; 1 << 20];
+            data.len()
+        });
+        let stats = stats.unwrap();
+        assert_eq!(len, 1 << 20);
+        assert!(stats.allocated >= 1 << 20);
+        assert!(stats.deallocated >= 1 << 20);
+        assert!(thread_allocated().unwrap() >= before + (1 << 20));
+
+        let scope = AllocationScope::new().unwrap();
+        let data = vec![1u8; 1 << 16];
+        assert!(scope.stats().net() >= 1 << 16);
+        drop(data);
+    }
+
+    #[tokio::test]
+    async fn measure_future() {
+        let (data, stats) = measure_future_allocations(async {
+            let mut data = Vec::new();
+            for _ in 0..4 {
+                data.push(vec![1u8; 1 << 16]);
+                tokio::task::yield_now().await;
+            }
+            data
+        })
+        .await;
+        assert_eq!(data.len(), 4);
+        assert!(stats.unwrap().net() >= 4 << 16);
+    }
+}
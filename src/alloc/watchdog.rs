@@ -0,0 +1,311 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::task::{declare_task_handle, TaskHandle};
+use super::{
+    cgroup_memory_limit_at, params, purge_all, JemallocError, CGROUP_ROOT, PROC_SELF_CGROUP,
+};
+
+/// Resident memory compared to the memory limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryPressure {
+    pub resident: u64,
+    pub limit: u64,
+    /// The threshold (a fraction of the limit) which was crossed.
+    pub threshold: f64,
+}
+
+impl MemoryPressure {
+    /// Fraction of the limit which is used.
+    pub fn usage(&self) -> f64 {
+        self.resident as f64 / self.limit as f64
+    }
+}
+
+type PressureCallback = Arc<dyn Fn(&MemoryPressure) + Send + Sync>;
+
+/// Background task which compares the resident memory with the cgroup limit.
+///
+/// Each threshold fires once when the usage goes above it and is rearmed
+/// when the usage drops below it again.
+#[derive(Clone)]
+pub struct MemoryWatchdog {
+    cgroup_root: PathBuf,
+    cgroup_membership: PathBuf,
+    limit: Option<u64>,
+    interval: Duration,
+    thresholds: Vec<f64>,
+    purge_threshold: Option<f64>,
+    #[cfg(all(feature = "alloc-profiling", unix))]
+    heap_dump: Option<(f64, super::profiling::ProfilingSupervisor)>,
+    callbacks: Vec<PressureCallback>,
+}
+
+impl Default for MemoryWatchdog {
+    fn default() -> Self {
+        Self {
+            cgroup_root: PathBuf::from(CGROUP_ROOT),
+            cgroup_membership: PathBuf::from(PROC_SELF_CGROUP),
+            limit: None,
+            interval: Duration::from_secs(1),
+            thresholds: vec![0.8, 0.9],
+            purge_threshold: Some(0.9),
+            #[cfg(all(feature = "alloc-profiling", unix))]
+            heap_dump: None,
+            callbacks: Vec::new(),
+        }
+    }
+}
+
+impl MemoryWatchdog {
+    /// Root of the cgroup filesystem. Default: [`CGROUP_ROOT`].
+    pub fn with_cgroup_root<P: Into<PathBuf>>(mut self, root: P) -> Self {
+        self.cgroup_root = root.into();
+        self
+    }
+
+    /// File with the cgroup membership of the process. Default: [`PROC_SELF_CGROUP`].
+    pub fn with_cgroup_membership<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.cgroup_membership = path.into();
+        self
+    }
+
+    /// Uses the explicit limit instead of the cgroup one.
+    pub fn with_limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Interval between checks. Default: 1s.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Soft thresholds as fractions of the limit. Default: `[0.8, 0.9]`.
+    pub fn with_thresholds<I: IntoIterator<Item = f64>>(mut self, thresholds: I) -> Self {
+        self.thresholds = thresholds.into_iter().collect();
+        self.thresholds.sort_by(f64::total_cmp);
+        self
+    }
+
+    /// Purges all arenas above the threshold. Default: `Some(0.9)`.
+    pub fn with_purge_threshold(mut self, threshold: Option<f64>) -> Self {
+        self.purge_threshold = threshold;
+        self
+    }
+
+    /// Dumps the heap profile above the threshold.
+    #[cfg(all(feature = "alloc-profiling", unix))]
+    pub fn with_heap_dump(
+        mut self,
+        threshold: f64,
+        dumps: super::profiling::ProfilingSupervisor,
+    ) -> Self {
+        self.heap_dump = Some((threshold, dumps));
+        self
+    }
+
+    /// Adds a callback which is called each time a soft threshold is crossed.
+    pub fn on_pressure<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&MemoryPressure) + Send + Sync + 'static,
+    {
+        self.callbacks.push(Arc::new(f));
+        self
+    }
+
+    /// Spawns the watchdog on the current tokio runtime.
+    ///
+    /// Fails if there is neither an explicit limit nor a cgroup limit.
+    pub fn spawn(self) -> std::io::Result<MemoryWatchdogHandle> {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => cgroup_memory_limit_at(&self.cgroup_root, &self.cgroup_membership)?
+                .ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::NotFound, "memory is not limited")
+                })?,
+        };
+
+        let task = TaskHandle::spawn(|mut stop_rx| async move {
+            let mut state = WatchdogState::default();
+            loop {
+                tokio::select! {
+                    _ = &mut stop_rx => break,
+                    _ = tokio::time::sleep(self.interval) => {}
+                }
+
+                let resident = match resident_bytes() {
+                    Ok(resident) => resident,
+                    Err(e) => {
+                        log::warn!("failed to fetch resident memory: {e}");
+                        continue;
+                    }
+                };
+
+                let actions = state.check(&self, resident, limit);
+                if !actions.is_empty() {
+                    let this = self.clone();
+                    tokio::task::spawn_blocking(move || this.apply(actions, resident, limit))
+                        .await
+                        .ok();
+                }
+            }
+        });
+
+        Ok(MemoryWatchdogHandle(task))
+    }
+
+    fn apply(&self, actions: Actions, resident: u64, limit: u64) {
+        for threshold in actions.crossed {
+            let pressure = MemoryPressure {
+                resident,
+                limit,
+                threshold,
+            };
+            log::warn!(
+                "resident memory is above {:.0}% of the limit: {resident} of {limit} bytes",
+                threshold * 100.0
+            );
+            for callback in &self.callbacks {
+                callback(&pressure);
+            }
+        }
+
+        if actions.purge {
+            match purge_all() {
+                Ok(report) => {
+                    log::warn!("purged {} bytes under memory pressure", report.released())
+                }
+                Err(e) => log::error!("failed to purge arenas: {e}"),
+            }
+        }
+
+        #[cfg(all(feature = "alloc-profiling", unix))]
+        if actions.dump {
+            if let Some((_, dumps)) = &self.heap_dump {
+                match dumps.dump_now() {
+                    Ok(path) => log::warn!("heap profile dumped under memory pressure to {path:?}"),
+                    Err(e) => log::error!("failed to dump the heap profile: {e}"),
+                }
+            }
+        }
+    }
+
+    fn heap_dump_threshold(&self) -> Option<f64> {
+        #[cfg(all(feature = "alloc-profiling", unix))]
+        return self.heap_dump.as_ref().map(|(threshold, _)| *threshold);
+        #[cfg(not(all(feature = "alloc-profiling", unix)))]
+        None
+    }
+}
+
+declare_task_handle! {
+    /// Handle of the spawned [`MemoryWatchdog`].
+    MemoryWatchdogHandle
+}
+
+fn resident_bytes() -> Result<u64, JemallocError> {
+    // Stats are cached. Need to advance epoch to refresh.
+    params::set_epoch(1)?;
+    Ok(params::stats_resident()? as u64)
+}
+
+#[derive(Default)]
+struct WatchdogState {
+    crossed: usize,
+    purged: bool,
+    dumped: bool,
+}
+
+#[derive(Default)]
+struct Actions {
+    crossed: Vec<f64>,
+    purge: bool,
+    dump: bool,
+}
+
+impl Actions {
+    fn is_empty(&self) -> bool {
+        self.crossed.is_empty() && !self.purge && !self.dump
+    }
+}
+
+impl WatchdogState {
+    fn check(&mut self, watchdog: &MemoryWatchdog, resident: u64, limit: u64) -> Actions {
+        let usage = resident as f64 / limit as f64;
+        let mut actions = Actions::default();
+
+        let crossed = watchdog.thresholds.iter().filter(|t| usage >= **t).count();
+        if crossed > self.crossed {
+            actions.crossed = watchdog.thresholds[self.crossed..crossed].to_vec();
+        }
+        self.crossed = crossed;
+
+        let edge = |threshold: Option<f64>, fired: &mut bool| match threshold {
+            Some(threshold) if usage >= threshold => !std::mem::replace(fired, true),
+            _ => {
+                *fired = false;
+                false
+            }
+        };
+        actions.purge = edge(watchdog.purge_threshold, &mut self.purged);
+        actions.dump = edge(watchdog.heap_dump_threshold(), &mut self.dumped);
+
+        actions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn thresholds() {
+        let watchdog = MemoryWatchdog::default()
+            .with_thresholds([0.9, 0.5])
+            .with_purge_threshold(Some(0.9));
+        let mut state = WatchdogState::default();
+
+        let actions = state.check(&watchdog, 40, 100);
+        assert!(actions.is_empty());
+
+        let actions = state.check(&watchdog, 95, 100);
+        assert_eq!(actions.crossed, [0.5, 0.9]);
+        assert!(actions.purge);
+        assert!(!actions.dump);
+
+        assert!(state.check(&watchdog, 96, 100).is_empty());
+
+        let actions = state.check(&watchdog, 60, 100);
+        assert!(actions.is_empty());
+
+        let actions = state.check(&watchdog, 91, 100);
+        assert_eq!(actions.crossed, [0.9]);
+        assert!(actions.purge);
+    }
+
+    #[tokio::test]
+    async fn watchdog_fires() {
+        let fired = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let handle = MemoryWatchdog::default()
+            .with_limit(1)
+            .with_interval(Duration::from_millis(10))
+            .with_thresholds([0.5])
+            .with_purge_threshold(None)
+            .on_pressure({
+                let fired = fired.clone();
+                move |pressure| {
+                    assert!(pressure.usage() > 1.0);
+                    fired.store(true, std::sync::atomic::Ordering::Release);
+                }
+            })
+            .spawn()
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        handle.stop().await;
+        assert!(fired.load(std::sync::atomic::Ordering::Acquire));
+    }
+}
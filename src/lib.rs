@@ -11,6 +11,10 @@ pub use self::serde_helpers::*;
 #[cfg(feature = "signal")]
 pub use self::signal::*;
 pub use self::time::*;
+#[cfg(feature = "tracing")]
+pub use self::tracing::*;
+#[cfg(feature = "alloc-tracking")]
+pub use self::tracking_alloc::*;
 #[cfg(feature = "web")]
 pub use self::web::*;
 
@@ -29,5 +33,9 @@ mod serde_helpers;
 #[cfg(feature = "signal")]
 mod signal;
 mod time;
+#[cfg(feature = "tracing")]
+mod tracing;
+#[cfg(feature = "alloc-tracking")]
+mod tracking_alloc;
 #[cfg(feature = "web")]
 mod web;
@@ -0,0 +1,121 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Runs the future with the key-value pair in the mapped diagnostic context.
+///
+/// The pair is visible to all records logged while the future is polled,
+/// regardless of the thread it is polled on. Values can be referenced
+/// in patterns as `{X(key)}` or `{X(key)(default)}` and are included
+/// into the `mdc` field by the `json` encoder.
+pub fn with_log_context<K, V, F>(key: K, value: V, future: F) -> WithLogContext<F>
+where
+    K: Into<String>,
+    V: Into<String>,
+    F: Future,
+{
+    WithLogContext {
+        key: key.into(),
+        value: value.into(),
+        future,
+    }
+}
+
+/// Adds the key-value pair to the mapped diagnostic context of the current
+/// thread until the returned guard is dropped.
+pub fn scoped_log_context<K, V>(key: K, value: V) -> log_mdc::InsertGuard
+where
+    K: Into<String>,
+    V: Into<String>,
+{
+    log_mdc::insert_scoped(key, value)
+}
+
+/// Future returned by [`with_log_context`].
+pub struct WithLogContext<F> {
+    key: String,
+    value: String,
+    future: F,
+}
+
+impl<F: Future> Future for WithLogContext<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `future` is structurally pinned and is never moved
+        let this = unsafe { self.get_unchecked_mut() };
+        let _guard = log_mdc::insert_scoped(this.key.as_str(), this.value.as_str());
+        unsafe { Pin::new_unchecked(&mut this.future) }.poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn context_in_encoders() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let config = serde_yaml::from_str::<serde_yaml::Value>(&format!(
+            r#"
+appenders:
+  pattern:
+    kind: file
+    path: {0}/pattern.log
+    encoder:
+      pattern: "{{X(request)(-)}} {{X(peer)(-)}} {{m}}{{n}}"
+  json:
+    kind: file
+    path: {0}/json.log
+    encoder:
+      kind: json
+root:
+  level: info
+  appenders:
+    - pattern
+    - json
+"#,
+            dir.path().display()
+        ))
+        .unwrap();
+
+        let config = crate::parse_logger_config(config).unwrap();
+        let log = |message: &str| {
+            for appender in config.appenders() {
+                appender
+                    .appender()
+                    .append(
+                        &log::Record::builder()
+                            .args(format_args!("{message}"))
+                            .level(log::Level::Info)
+                            .build(),
+                    )
+                    .unwrap();
+            }
+        };
+
+        with_log_context("request", "1", async {
+            log("first");
+            tokio::task::yield_now().await;
+            with_log_context("peer", "abc", async {
+                log("second");
+            })
+            .await;
+            log("third");
+        })
+        .await;
+        log("fourth");
+
+        let output = std::fs::read_to_string(dir.path().join("pattern.log")).unwrap();
+        assert_eq!(output, "1 - first\n1 abc second\n1 - third\n- - fourth\n");
+
+        let output = std::fs::read_to_string(dir.path().join("json.log")).unwrap();
+        let mdc = output
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["mdc"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(mdc[1], serde_json::json!({ "request": "1", "peer": "abc" }));
+        assert_eq!(mdc[3], serde_json::json!({}));
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use log::LevelFilter;
+use log4rs::append::console::{ConsoleAppender, Target};
+use log4rs::append::file::FileAppender;
+use log4rs::append::rolling_file::RollingFileAppender;
+use log4rs::config::{Appender, Logger, Root};
+use log4rs::encode::json::JsonEncoder;
+use log4rs::encode::pattern::PatternEncoder;
+use log4rs::encode::Encode;
+use log4rs::filter::threshold::ThresholdFilter;
+use serde::{Deserialize, Serialize};
+
+use super::{LoggerError, RetentionPolicy, RetentionPolicyConfig};
+
+/// Typed logger config, an alternative to the raw `log4rs` YAML.
+///
+/// ```yaml
+/// level: info
+/// console: {}
+/// rolling_file:
+///   path: logs/node.log
+///   policy:
+///     rotation: daily
+///     compress: true
+///     max_count: 7
+/// loggers:
+///   tokio: warn
+///   my_app::db: debug
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoggerConfig {
+    /// Root log level.
+    #[serde(default = "default_level")]
+    pub level: LevelFilter,
+    /// Default encoder for all appenders.
+    #[serde(default)]
+    pub encoder: LogEncoderConfig,
+    #[serde(default)]
+    pub console: Option<ConsoleLogConfig>,
+    #[serde(default)]
+    pub file: Option<FileLogConfig>,
+    #[serde(default)]
+    pub rolling_file: Option<RollingFileLogConfig>,
+    #[cfg(feature = "metrics")]
+    #[serde(default)]
+    pub metrics: Option<super::MetricsAppenderConfig>,
+    /// Per-module log level overrides.
+    #[serde(default)]
+    pub loggers: BTreeMap<String, LevelFilter>,
+}
+
+impl Default for LoggerConfig {
+    fn default() -> Self {
+        Self {
+            level: default_level(),
+            encoder: Default::default(),
+            console: Some(Default::default()),
+            file: None,
+            rolling_file: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            loggers: Default::default(),
+        }
+    }
+}
+
+impl LoggerConfig {
+    /// Creates an empty config without appenders.
+    pub fn new(level: LevelFilter) -> Self {
+        Self {
+            level,
+            console: None,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_encoder(mut self, encoder: LogEncoderConfig) -> Self {
+        self.encoder = encoder;
+        self
+    }
+
+    pub fn with_console(mut self, console: ConsoleLogConfig) -> Self {
+        self.console = Some(console);
+        self
+    }
+
+    pub fn with_file(mut self, file: FileLogConfig) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    pub fn with_rolling_file(mut self, rolling_file: RollingFileLogConfig) -> Self {
+        self.rolling_file = Some(rolling_file);
+        self
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, metrics: super::MetricsAppenderConfig) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn with_logger<T: Into<String>>(mut self, module: T, level: LevelFilter) -> Self {
+        self.loggers.insert(module.into(), level);
+        self
+    }
+
+    /// Builds `log4rs` config. Log files are opened at this point.
+    pub fn build(&self) -> Result<log4rs::Config, LoggerError> {
+        let mut appenders = Vec::new();
+
+        if let Some(console) = &self.console {
+            let appender = ConsoleAppender::builder()
+                .encoder(self.encoder_for(&console.encoder))
+                .target(match console.target {
+                    ConsoleTarget::Stdout => Target::Stdout,
+                    ConsoleTarget::Stderr => Target::Stderr,
+                })
+                .build();
+            appenders.push(build_appender("console", console.level, Box::new(appender)));
+        }
+
+        if let Some(file) = &self.file {
+            let appender = FileAppender::builder()
+                .encoder(self.encoder_for(&file.encoder))
+                .append(file.append)
+                .build(&file.path)
+                .map_err(LoggerError::AppenderError)?;
+            appenders.push(build_appender("file", file.level, Box::new(appender)));
+        }
+
+        if let Some(rolling_file) = &self.rolling_file {
+            let policy = RetentionPolicy::new(rolling_file.policy.clone());
+            let appender = RollingFileAppender::builder()
+                .encoder(self.encoder_for(&rolling_file.encoder))
+                .build(&rolling_file.path, Box::new(policy))
+                .map_err(LoggerError::AppenderError)?;
+            appenders.push(build_appender(
+                "rolling_file",
+                rolling_file.level,
+                Box::new(appender),
+            ));
+        }
+
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            let appender = super::MetricsAppender::new(metrics.clone());
+            appenders.push(build_appender("metrics", None, Box::new(appender)));
+        }
+
+        let names = appenders
+            .iter()
+            .map(|appender| appender.name().to_owned())
+            .collect::<Vec<_>>();
+
+        let loggers = self
+            .loggers
+            .iter()
+            .map(|(module, level)| Logger::builder().build(module, *level));
+
+        log4rs::Config::builder()
+            .appenders(appenders)
+            .loggers(loggers)
+            .build(Root::builder().appenders(names).build(self.level))
+            .map_err(LoggerError::BuildError)
+    }
+
+    fn encoder_for(&self, encoder: &Option<LogEncoderConfig>) -> Box<dyn Encode> {
+        encoder.as_ref().unwrap_or(&self.encoder).build()
+    }
+}
+
+impl TryFrom<&LoggerConfig> for log4rs::Config {
+    type Error = LoggerError;
+
+    fn try_from(config: &LoggerConfig) -> Result<Self, Self::Error> {
+        config.build()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LogEncoderConfig {
+    Pattern {
+        #[serde(default = "default_pattern")]
+        pattern: String,
+    },
+    Json,
+}
+
+impl Default for LogEncoderConfig {
+    fn default() -> Self {
+        Self::Pattern {
+            pattern: default_pattern(),
+        }
+    }
+}
+
+impl LogEncoderConfig {
+    fn build(&self) -> Box<dyn Encode> {
+        match self {
+            Self::Pattern { pattern } => Box::new(PatternEncoder::new(pattern)),
+            Self::Json => Box::new(JsonEncoder::new()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsoleTarget {
+    #[default]
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConsoleLogConfig {
+    #[serde(default)]
+    pub target: ConsoleTarget,
+    /// Min level of records written by this appender.
+    #[serde(default)]
+    pub level: Option<LevelFilter>,
+    /// Overrides the default encoder.
+    #[serde(default)]
+    pub encoder: Option<LogEncoderConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileLogConfig {
+    pub path: PathBuf,
+    /// Append to the existing file instead of truncating it.
+    #[serde(default = "default_append")]
+    pub append: bool,
+    /// Min level of records written by this appender.
+    #[serde(default)]
+    pub level: Option<LevelFilter>,
+    /// Overrides the default encoder.
+    #[serde(default)]
+    pub encoder: Option<LogEncoderConfig>,
+}
+
+impl FileLogConfig {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            append: default_append(),
+            level: None,
+            encoder: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RollingFileLogConfig {
+    pub path: PathBuf,
+    /// Min level of records written by this appender.
+    #[serde(default)]
+    pub level: Option<LevelFilter>,
+    /// Overrides the default encoder.
+    #[serde(default)]
+    pub encoder: Option<LogEncoderConfig>,
+    /// Rotation and retention of rolled files.
+    #[serde(default)]
+    pub policy: RetentionPolicyConfig,
+}
+
+impl RollingFileLogConfig {
+    pub fn new<P: Into<PathBuf>>(path: P, policy: RetentionPolicyConfig) -> Self {
+        Self {
+            path: path.into(),
+            level: None,
+            encoder: None,
+            policy,
+        }
+    }
+}
+
+fn build_appender(
+    name: &str,
+    level: Option<LevelFilter>,
+    appender: Box<dyn log4rs::append::Append>,
+) -> Appender {
+    let mut builder = Appender::builder();
+    if let Some(level) = level {
+        builder = builder.filter(Box::new(ThresholdFilter::new(level)));
+    }
+    builder.build(name, appender)
+}
+
+fn default_level() -> LevelFilter {
+    LevelFilter::Info
+}
+
+fn default_pattern() -> String {
+    "{d} {l} {t} - {m}{n}".to_owned()
+}
+
+fn default_append() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RotationPeriod;
+
+    #[test]
+    fn parse_typed_config() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let config = serde_yaml::from_str::<LoggerConfig>(&format!(
+            r#"
+level: debug
+encoder:
+  kind: json
+console:
+  target: stderr
+  level: warn
+  encoder:
+    kind: pattern
+    pattern: "{{l}} {{m}}{{n}}"
+rolling_file:
+  path: {}/node.log
+  policy:
+    rotation: daily
+    max_size: 10 mb
+    compress: true
+    max_count: 7
+    max_age: 7days
+loggers:
+  tokio: warn
+  hyper::proto: error
+"#,
+            dir.path().display()
+        ))
+        .unwrap();
+
+        let rolling_file = config.rolling_file.as_ref().unwrap();
+        assert_eq!(rolling_file.policy.rotation, Some(RotationPeriod::Daily));
+        assert_eq!(rolling_file.policy.max_size, Some(10 << 20));
+
+        let built = config.build().unwrap();
+        assert_eq!(built.root().level(), LevelFilter::Debug);
+        assert_eq!(built.root().appenders(), ["console", "rolling_file"]);
+        assert_eq!(built.appenders()[0].filters().len(), 1);
+        assert_eq!(built.loggers().len(), 2);
+
+        // Serialized config is parsed back
+        let serialized = serde_yaml::to_string(&config).unwrap();
+        let parsed = serde_yaml::from_str::<LoggerConfig>(&serialized).unwrap();
+        assert_eq!(
+            parsed.rolling_file.unwrap().policy.max_age,
+            rolling_file.policy.max_age
+        );
+    }
+
+    #[test]
+    fn reject_unknown_rolling_file_keys() {
+        for config in [
+            "rolling_file:\n  path: node.log\n  max_count: 7\n",
+            "rolling_file:\n  path: node.log\n  policy:\n    max_cout: 7\n",
+        ] {
+            assert!(serde_yaml::from_str::<LoggerConfig>(config).is_err());
+        }
+
+        let config =
+            serde_yaml::from_str::<LoggerConfig>("rolling_file:\n  path: node.log\n").unwrap();
+        assert_eq!(config.rolling_file.unwrap().policy.max_count, None);
+    }
+
+    #[test]
+    fn build_typed_config() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let config = LoggerConfig::new(LevelFilter::Info)
+            .with_console(Default::default())
+            .with_file(FileLogConfig::new(dir.path().join("node.log")))
+            .with_logger("tokio", LevelFilter::Warn)
+            .build()
+            .unwrap();
+
+        assert_eq!(config.root().appenders(), ["console", "file"]);
+        assert_eq!(config.loggers()[0].name(), "tokio");
+        assert_eq!(config.loggers()[0].level(), LevelFilter::Warn);
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, RwLock};
+
+use log::{Level, Record};
+use log4rs::append::Append;
+use log4rs::config::{Deserialize, Deserializers};
+use metrics::{Key, KeyName, Label, SharedString};
+
+/// Label value used for targets which didn't fit into `max_targets`.
+pub const OTHER_TARGETS_LABEL: &str = "other";
+
+/// Appender which increments a `metrics` counter for each log record.
+///
+/// Counters are labeled by `level` and `target`. The number of distinct
+/// targets is limited by `max_targets`, all others are reported as
+/// [`OTHER_TARGETS_LABEL`].
+#[derive(Debug)]
+pub struct MetricsAppender {
+    metric: KeyName,
+    target_depth: Option<NonZeroUsize>,
+    max_targets: usize,
+    known_targets: RwLock<HashMap<String, TargetKeys>>,
+    other_targets: TargetKeys,
+}
+
+/// Counter keys of a single target for each level.
+type TargetKeys = [Key; 5];
+
+static METADATA: metrics::Metadata<'static> =
+    metrics::Metadata::new(module_path!(), metrics::Level::INFO, Some(module_path!()));
+
+impl MetricsAppender {
+    pub fn new(config: MetricsAppenderConfig) -> Self {
+        let metric = KeyName::from(SharedString::from_shared(Arc::<str>::from(config.metric)));
+        let other_targets = make_target_keys(&metric, SharedString::const_str(OTHER_TARGETS_LABEL));
+
+        Self {
+            metric,
+            target_depth: config.target_depth,
+            max_targets: config.max_targets,
+            known_targets: Default::default(),
+            other_targets,
+        }
+    }
+
+    fn truncate_target<'a>(&self, target: &'a str) -> &'a str {
+        match self.target_depth {
+            Some(depth) => match target.match_indices("::").nth(depth.get() - 1) {
+                Some((i, _)) => &target[..i],
+                None => target,
+            },
+            None => target,
+        }
+    }
+
+    /// Returns the counter key of the target and level.
+    ///
+    /// Keys are built once per target and are cheap to clone, so known targets
+    /// don't allocate. The lock is released before the key is returned, because
+    /// the recorder can log and re-enter the appender.
+    fn key(&self, target: &str, level: Level) -> Key {
+        let target = self.truncate_target(target);
+        let index = level as usize - 1;
+
+        if let Some(keys) = self.known_targets.read().unwrap().get(target) {
+            return keys[index].clone();
+        }
+
+        let mut known_targets = self.known_targets.write().unwrap();
+        if !known_targets.contains_key(target) && known_targets.len() >= self.max_targets {
+            return self.other_targets[index].clone();
+        }
+
+        let keys = known_targets.entry(target.to_owned()).or_insert_with(|| {
+            let label = SharedString::from_shared(Arc::<str>::from(target));
+            make_target_keys(&self.metric, label)
+        });
+        keys[index].clone()
+    }
+}
+
+fn make_target_keys(metric: &KeyName, target: SharedString) -> TargetKeys {
+    [
+        Level::Error,
+        Level::Warn,
+        Level::Info,
+        Level::Debug,
+        Level::Trace,
+    ]
+    .map(|level| {
+        Key::from_parts(
+            metric.clone(),
+            vec![
+                Label::new("level", level.as_str()),
+                Label::new("target", target.clone()),
+            ],
+        )
+    })
+}
+
+impl Append for MetricsAppender {
+    fn append(&self, record: &Record) -> anyhow::Result<()> {
+        let key = self.key(record.target(), record.level());
+        metrics::with_recorder(|recorder| recorder.register_counter(&key, &METADATA)).increment(1);
+        Ok(())
+    }
+
+    fn flush(&self) {}
+}
+
+/// Config of the `metrics` appender kind.
+///
+/// ```yaml
+/// appenders:
+///   metrics:
+///     kind: metrics
+///     metric: log_records_total
+///     target_depth: 2
+///     max_targets: 100
+/// ```
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsAppenderConfig {
+    /// Counter name.
+    #[serde(default = "default_metric")]
+    pub metric: String,
+    /// Number of module path segments to keep in the `target` label.
+    /// Must be greater than zero.
+    #[serde(default)]
+    pub target_depth: Option<NonZeroUsize>,
+    /// Max number of distinct `target` label values.
+    #[serde(default = "default_max_targets")]
+    pub max_targets: usize,
+}
+
+impl Default for MetricsAppenderConfig {
+    fn default() -> Self {
+        Self {
+            metric: default_metric(),
+            target_depth: None,
+            max_targets: default_max_targets(),
+        }
+    }
+}
+
+fn default_metric() -> String {
+    "log_records_total".to_owned()
+}
+
+fn default_max_targets() -> usize {
+    100
+}
+
+/// Deserializer for the `metrics` appender kind.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MetricsAppenderDeserializer;
+
+impl Deserialize for MetricsAppenderDeserializer {
+    type Trait = dyn Append;
+    type Config = MetricsAppenderConfig;
+
+    fn deserialize(
+        &self,
+        config: Self::Config,
+        _: &Deserializers,
+    ) -> anyhow::Result<Box<Self::Trait>> {
+        Ok(Box::new(MetricsAppender::new(config)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn target_labels() {
+        let appender = MetricsAppender::new(MetricsAppenderConfig {
+            target_depth: NonZeroUsize::new(2),
+            max_targets: 2,
+            ..Default::default()
+        });
+
+        let labels = |target: &str, level: Level| {
+            let key = appender.key(target, level);
+            assert_eq!(key.name(), "log_records_total");
+            key.labels()
+                .map(|label| label.value().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(labels("first", Level::Info), ["INFO", "first"]);
+        assert_eq!(labels("second::a::b", Level::Warn), ["WARN", "second::a"]);
+        assert_eq!(labels("second::a::c", Level::Error), ["ERROR", "second::a"]);
+        assert_eq!(
+            labels("third", Level::Trace),
+            ["TRACE", OTHER_TARGETS_LABEL]
+        );
+        assert_eq!(labels("first", Level::Debug), ["DEBUG", "first"]);
+    }
+
+    #[test]
+    fn parse_metrics_appender() {
+        let config = serde_yaml::from_str::<serde_yaml::Value>(
+            r#"
+appenders:
+  metrics:
+    kind: metrics
+    metric: test_log_records
+    max_targets: 10
+root:
+  level: info
+  appenders:
+    - metrics
+"#,
+        )
+        .unwrap();
+
+        let config = crate::parse_logger_config(config).unwrap();
+        assert_eq!(config.appenders().len(), 1);
+
+        let zero_depth = serde_yaml::from_str::<MetricsAppenderConfig>("target_depth: 0");
+        assert!(zero_depth.is_err());
+    }
+}
@@ -1,12 +1,31 @@
+use log4rs::config::Deserializers;
+
+pub use self::context::*;
+pub use self::logger_config::*;
+#[cfg(feature = "metrics")]
+pub use self::metrics_appender::*;
+pub use self::retention::*;
+
+mod context;
+mod logger_config;
+#[cfg(feature = "metrics")]
+mod metrics_appender;
+mod retention;
+
 pub fn init_logger(initial_value: &serde_yaml::Value) -> Result<log4rs::Handle, LoggerError> {
     let handle = log4rs::config::init_config(parse_logger_config(initial_value.clone())?)?;
     Ok(handle)
 }
 
+pub fn init_logger_from_config(config: &LoggerConfig) -> Result<log4rs::Handle, LoggerError> {
+    let handle = log4rs::config::init_config(config.build()?)?;
+    Ok(handle)
+}
+
 pub fn parse_logger_config(value: serde_yaml::Value) -> Result<log4rs::Config, LoggerError> {
     let config = serde_yaml::from_value::<log4rs::config::RawConfig>(value)?;
 
-    let (appenders, errors) = config.appenders_lossy(&log4rs::config::Deserializers::default());
+    let (appenders, errors) = config.appenders_lossy(&logger_deserializers());
     if !errors.is_empty() {
         return Err(LoggerError::InvalidAppenders(format!("{errors:#?}")));
     }
@@ -18,12 +37,26 @@ pub fn parse_logger_config(value: serde_yaml::Value) -> Result<log4rs::Config, L
         .map_err(LoggerError::BuildError)
 }
 
+/// Default `log4rs` deserializers extended with the custom kinds from this crate.
+pub fn logger_deserializers() -> Deserializers {
+    let mut deserializers = Deserializers::default();
+
+    deserializers.insert("retention", RetentionPolicyDeserializer);
+
+    #[cfg(feature = "metrics")]
+    deserializers.insert("metrics", MetricsAppenderDeserializer);
+
+    deserializers
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum LoggerError {
     #[error("bad config")]
     InvalidConfig(#[from] serde_yaml::Error),
     #[error("invalid appenders: {0}")]
     InvalidAppenders(String),
+    #[error("failed to open log file")]
+    AppenderError(#[source] std::io::Error),
     #[error("failed to build logger")]
     BuildError(#[from] log4rs::config::runtime::ConfigErrors),
     #[error("failed to set logger")]
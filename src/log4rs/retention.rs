@@ -0,0 +1,576 @@
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use chrono::Timelike;
+
+use log4rs::append::rolling_file::policy::Policy;
+use log4rs::append::rolling_file::LogFile;
+use log4rs::config::{Deserialize, Deserializers};
+use serde::de;
+
+/// Rolling file policy with size and time triggers, gzip compression
+/// and retention of the rolled files.
+///
+/// Rolled files are placed next to the active log file and named
+/// `<file name>.<UTC timestamp>[.gz]`, e.g. `node.log.20221018-000000.gz`.
+///
+/// ```yaml
+/// appenders:
+///   file:
+///     kind: rolling_file
+///     path: logs/node.log
+///     policy:
+///       kind: retention
+///       max_size: 100 mb
+///       rotation: daily
+///       compress: true
+///       max_count: 10
+///       max_age: 7days
+/// ```
+#[derive(Debug)]
+pub struct RetentionPolicy {
+    max_size: Option<u64>,
+    rotation: Option<RotationPeriod>,
+    archiver: LogArchiver,
+    current_period: Mutex<Option<u64>>,
+}
+
+impl RetentionPolicy {
+    pub fn new(config: RetentionPolicyConfig) -> Self {
+        Self {
+            max_size: config.max_size,
+            rotation: config.rotation,
+            archiver: LogArchiver {
+                compress: config.compress,
+                max_count: config.max_count,
+                max_age: config.max_age,
+            },
+            current_period: Default::default(),
+        }
+    }
+
+    fn should_roll(&self, path: &Path, len: u64, now: SystemTime) -> bool {
+        if matches!(self.max_size, Some(max_size) if len > max_size) {
+            return true;
+        }
+
+        let Some(rotation) = self.rotation else {
+            return false;
+        };
+
+        let now = rotation.period_of(now);
+        let mut current = self.current_period.lock().unwrap();
+        let current = current.get_or_insert_with(|| {
+            // Resume the period of the file which was left after restart
+            std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .map(|modified| rotation.period_of(modified))
+                .unwrap_or(now)
+        });
+
+        if *current < now {
+            *current = now;
+            len > 0
+        } else {
+            false
+        }
+    }
+}
+
+impl Policy for RetentionPolicy {
+    fn process(&self, log: &mut LogFile) -> anyhow::Result<()> {
+        let now = SystemTime::now();
+        if self.should_roll(log.path(), log.len_estimate(), now) {
+            log.roll();
+            self.archiver.archive(log.path(), now)?;
+            self.archiver.prune(log.path(), now)?;
+        }
+        Ok(())
+    }
+
+    fn is_pre_process(&self) -> bool {
+        true
+    }
+}
+
+/// Moves rolled log files aside and removes the outdated ones.
+#[derive(Debug, Clone)]
+struct LogArchiver {
+    compress: bool,
+    max_count: Option<usize>,
+    max_age: Option<Duration>,
+}
+
+impl LogArchiver {
+    fn archive(&self, path: &Path, now: SystemTime) -> std::io::Result<PathBuf> {
+        let timestamp = chrono::DateTime::<chrono::Utc>::from(now)
+            .naive_utc()
+            .with_nanosecond(0)
+            .unwrap_or_default();
+
+        // Continue the numbering after the newest file of the same second,
+        // so that a new file is never ranked older than the existing ones
+        let index = self
+            .list_rolled(path)?
+            .into_iter()
+            .filter(|(id, _)| id.timestamp == timestamp)
+            .map(|(id, _)| id.index + 1)
+            .max()
+            .unwrap_or_default();
+
+        let id = RolledFileId { timestamp, index };
+        let mut target = path.as_os_str().to_owned();
+        target.push(format!(".{id}"));
+        if self.compress {
+            target.push(".gz");
+        }
+        let target = PathBuf::from(target);
+
+        if self.compress {
+            let mut input = File::open(path)?;
+            let output = BufWriter::new(File::create(&target)?);
+            let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+            std::io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?.into_inner()?.sync_all()?;
+            std::fs::remove_file(path)?;
+        } else {
+            std::fs::rename(path, &target)?;
+        }
+
+        Ok(target)
+    }
+
+    fn prune(&self, path: &Path, now: SystemTime) -> std::io::Result<()> {
+        if self.max_count.is_none() && self.max_age.is_none() {
+            return Ok(());
+        }
+
+        let mut rolled = self.list_rolled(path)?;
+
+        // Newest first
+        rolled.sort_unstable_by_key(|(id, _)| std::cmp::Reverse(*id));
+
+        for (i, (id, rolled)) in rolled.iter().enumerate() {
+            let too_many = matches!(self.max_count, Some(max_count) if i >= max_count);
+            let too_old = matches!(
+                self.max_age,
+                Some(max_age) if now.duration_since(id.time()).unwrap_or_default() > max_age
+            );
+
+            if too_many || too_old {
+                std::fs::remove_file(rolled)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns rolled files of the log file.
+    fn list_rolled(&self, path: &Path) -> std::io::Result<Vec<(RolledFileId, PathBuf)>> {
+        let Some(file_name) = path.file_name() else {
+            return Ok(Vec::new());
+        };
+        let prefix = format!("{}.", file_name.to_string_lossy());
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let mut rolled = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let id = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(&prefix))
+                .and_then(RolledFileId::parse);
+            if let Some(id) = id {
+                rolled.push((id, entry.path()));
+            }
+        }
+        Ok(rolled)
+    }
+}
+
+/// Suffix of the rolled file name: `<YYYYmmdd-HHMMSS>[-N][.gz]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct RolledFileId {
+    timestamp: chrono::NaiveDateTime,
+    index: u32,
+}
+
+impl RolledFileId {
+    const TIMESTAMP_FORMAT: &'static str = "%Y%m%d-%H%M%S";
+    const TIMESTAMP_LEN: usize = "YYYYmmdd-HHMMSS".len();
+
+    fn parse(suffix: &str) -> Option<Self> {
+        let suffix = suffix.strip_suffix(".gz").unwrap_or(suffix);
+        if suffix.len() < Self::TIMESTAMP_LEN || !suffix.is_char_boundary(Self::TIMESTAMP_LEN) {
+            return None;
+        }
+
+        let (timestamp, index) = suffix.split_at(Self::TIMESTAMP_LEN);
+        let timestamp =
+            chrono::NaiveDateTime::parse_from_str(timestamp, Self::TIMESTAMP_FORMAT).ok()?;
+        let index = match index {
+            "" => 0,
+            index => {
+                let index = index.strip_prefix('-')?;
+                if !index.bytes().all(|c| c.is_ascii_digit()) {
+                    return None;
+                }
+                index.parse().ok()?
+            }
+        };
+
+        Some(Self { timestamp, index })
+    }
+
+    fn time(&self) -> SystemTime {
+        SystemTime::from(chrono::DateTime::<chrono::Utc>::from_naive_utc_and_offset(
+            self.timestamp,
+            chrono::Utc,
+        ))
+    }
+}
+
+impl fmt::Display for RolledFileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.timestamp.format(Self::TIMESTAMP_FORMAT).fmt(f)?;
+        if self.index > 0 {
+            write!(f, "-{}", self.index)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RotationPeriod {
+    Hourly,
+    Daily,
+}
+
+impl RotationPeriod {
+    fn as_secs(&self) -> u64 {
+        match self {
+            Self::Hourly => 3600,
+            Self::Daily => 86400,
+        }
+    }
+
+    /// Index of the UTC-aligned period containing the specified time.
+    fn period_of(&self, time: SystemTime) -> u64 {
+        let secs = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        secs / self.as_secs()
+    }
+}
+
+/// Config of the `retention` policy kind.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionPolicyConfig {
+    /// Roll the file when it exceeds this size (e.g. `100 mb`).
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub max_size: Option<u64>,
+    /// Roll the file at the start of each UTC hour or day.
+    #[serde(default)]
+    pub rotation: Option<RotationPeriod>,
+    /// Gzip rolled files.
+    #[serde(default)]
+    pub compress: bool,
+    /// Max number of rolled files to keep.
+    #[serde(default)]
+    pub max_count: Option<usize>,
+    /// Remove rolled files older than this (e.g. `7days`).
+    #[serde(
+        default,
+        serialize_with = "serialize_age",
+        deserialize_with = "deserialize_age"
+    )]
+    pub max_age: Option<Duration>,
+}
+
+/// Deserializer for the `retention` policy kind.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetentionPolicyDeserializer;
+
+impl Deserialize for RetentionPolicyDeserializer {
+    type Trait = dyn Policy;
+    type Config = RetentionPolicyConfig;
+
+    fn deserialize(
+        &self,
+        config: Self::Config,
+        _: &Deserializers,
+    ) -> anyhow::Result<Box<Self::Trait>> {
+        Ok(Box::new(RetentionPolicy::new(config)))
+    }
+}
+
+fn serialize_age<S>(value: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match value {
+        Some(value) => serializer.collect_str(&humantime::format_duration(*value)),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn deserialize_age<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let Some(value) = <Option<String> as serde::Deserialize>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    humantime::parse_duration(&value)
+        .map(Some)
+        .map_err(de::Error::custom)
+}
+
+fn deserialize_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    struct SizeVisitor;
+
+    impl<'de> de::Visitor<'de> for SizeVisitor {
+        type Value = Option<u64>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a size in bytes or a string like `10 mb`")
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: de::Deserializer<'de>,
+        {
+            deserializer.deserialize_any(self)
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            Ok(Some(v))
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            u64::try_from(v)
+                .map(Some)
+                .map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            let invalid = || E::invalid_value(de::Unexpected::Str(v), &self);
+
+            let (number, unit) = match v.find(|c: char| !c.is_ascii_digit()) {
+                Some(n) => (v[..n].trim(), v[n..].trim()),
+                None => (v.trim(), ""),
+            };
+            let number = number.parse::<u64>().map_err(|_| invalid())?;
+
+            let multiplier: u64 = match unit.to_ascii_lowercase().as_str() {
+                "" | "b" => 1,
+                "kb" | "kib" => 1 << 10,
+                "mb" | "mib" => 1 << 20,
+                "gb" | "gib" => 1 << 30,
+                "tb" | "tib" => 1 << 40,
+                _ => return Err(invalid()),
+            };
+
+            number.checked_mul(multiplier).map(Some).ok_or_else(invalid)
+        }
+    }
+
+    deserializer.deserialize_option(SizeVisitor)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use super::*;
+
+    fn list_rolled(dir: &Path) -> Vec<String> {
+        let mut names = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name != "node.log" && name != "node.log.json")
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn size_rotation_with_retention() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node.log");
+
+        // Active file of another appender
+        let other = dir.path().join("node.log.json");
+        std::fs::write(&other, "{}").unwrap();
+
+        let config = serde_yaml::from_str::<serde_yaml::Value>(&format!(
+            r#"
+appenders:
+  file:
+    kind: rolling_file
+    path: {}
+    encoder:
+      pattern: "{{m}}{{n}}"
+    policy:
+      kind: retention
+      max_size: 15 b
+      compress: true
+      max_count: 3
+root:
+  level: info
+  appenders:
+    - file
+"#,
+            path.display()
+        ))
+        .unwrap();
+
+        let config = crate::parse_logger_config(config).unwrap();
+        let appender = config.appenders()[0].appender();
+
+        for i in 0..10 {
+            appender
+                .append(
+                    &log::Record::builder()
+                        .args(format_args!("record number {i}"))
+                        .level(log::Level::Info)
+                        .build(),
+                )
+                .unwrap();
+        }
+
+        let rolled = list_rolled(dir.path());
+        assert_eq!(rolled.len(), 3);
+        assert!(rolled.iter().all(|name| name.ends_with(".gz")));
+
+        // Only the newest rolled files are kept
+        let mut records = rolled
+            .iter()
+            .map(|name| {
+                let mut data = String::new();
+                flate2::read::GzDecoder::new(File::open(dir.path().join(name)).unwrap())
+                    .read_to_string(&mut data)
+                    .unwrap();
+                data
+            })
+            .collect::<Vec<_>>();
+        records.sort();
+        assert_eq!(
+            records,
+            [
+                "record number 6\n",
+                "record number 7\n",
+                "record number 8\n"
+            ]
+        );
+
+        let active = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(active, "record number 9\n");
+        assert!(other.exists());
+    }
+
+    #[test]
+    fn time_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node.log");
+
+        let policy = RetentionPolicy::new(RetentionPolicyConfig {
+            rotation: Some(RotationPeriod::Daily),
+            ..Default::default()
+        });
+
+        // 2022-01-08 01:00:00 UTC, the file doesn't exist yet so the period starts now
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(19000 * 86400 + 3600);
+        assert!(!policy.should_roll(&path, 11, now));
+        assert!(!policy.should_roll(&path, 11, now + Duration::from_secs(1)));
+        assert!(policy.should_roll(&path, 11, now + Duration::from_secs(86400)));
+        assert!(!policy.should_roll(&path, 11, now + Duration::from_secs(86401)));
+
+        std::fs::write(&path, "old record\n").unwrap();
+        let archiver = LogArchiver {
+            compress: false,
+            max_count: None,
+            max_age: Some(Duration::from_secs(3600)),
+        };
+        let rolled = archiver.archive(&path, now).unwrap();
+        assert!(!path.exists());
+        assert_eq!(
+            rolled.file_name().unwrap().to_string_lossy(),
+            "node.log.20220108-010000"
+        );
+        assert_eq!(std::fs::read_to_string(&rolled).unwrap(), "old record\n");
+
+        archiver.prune(&path, now).unwrap();
+        assert!(rolled.exists());
+
+        archiver
+            .prune(&path, now + Duration::from_secs(7200))
+            .unwrap();
+        assert!(!rolled.exists());
+    }
+
+    #[test]
+    fn rolled_file_order() {
+        let parse = |suffix: &str| RolledFileId::parse(suffix);
+        assert_eq!(parse("json"), None);
+        assert_eq!(parse("20221018-000000.json"), None);
+        assert_eq!(parse("20221018-000000-"), None);
+        assert_eq!(parse("20221018-000000-+1"), None);
+
+        let mut ids = [
+            "20221018-000000-10.gz",
+            "20221018-000000.gz",
+            "20221018-000000-9.gz",
+            "20221017-235959-3",
+        ]
+        .map(|suffix| parse(suffix).unwrap());
+        ids.sort();
+        assert_eq!(
+            ids.map(|id| id.to_string()),
+            [
+                "20221017-235959-3",
+                "20221018-000000",
+                "20221018-000000-9",
+                "20221018-000000-10"
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_sizes() {
+        #[derive(serde::Deserialize)]
+        struct Test {
+            #[serde(default, deserialize_with = "deserialize_size")]
+            size: Option<u64>,
+        }
+
+        let parse = |s: &str| serde_yaml::from_str::<Test>(s).map(|t| t.size).ok();
+        assert_eq!(parse("size: 123"), Some(Some(123)));
+        assert_eq!(parse("size: 10 mb"), Some(Some(10 << 20)));
+        assert_eq!(parse("size: 1GiB"), Some(Some(1 << 30)));
+        assert_eq!(parse("{}"), Some(None));
+        assert_eq!(parse("size: 10 parsecs"), None);
+    }
+}
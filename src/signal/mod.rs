@@ -0,0 +1,253 @@
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_util::StreamExt;
+use tokio::signal::unix;
+
+pub use self::router::{HandlerId, HandlerMode, SignalRouter};
+pub use self::shutdown::{Shutdown, ShutdownPhase, ShutdownReport, ESCALATION_EXIT_CODE};
+
+mod router;
+mod shutdown;
+
+pub const TERMINATION_SIGNALS: [libc::c_int; 5] = [
+    libc::SIGINT,
+    libc::SIGTERM,
+    libc::SIGQUIT,
+    libc::SIGABRT,
+    20, // SIGTSTP
+];
+
+/// Signal number with its name (e.g. `SIGTERM (15)`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SignalId {
+    pub number: libc::c_int,
+    pub name: &'static str,
+}
+
+impl SignalId {
+    pub fn new(number: libc::c_int) -> Self {
+        Self {
+            number,
+            name: signal_name(number),
+        }
+    }
+}
+
+impl From<libc::c_int> for SignalId {
+    fn from(number: libc::c_int) -> Self {
+        Self::new(number)
+    }
+}
+
+impl From<unix::SignalKind> for SignalId {
+    fn from(kind: unix::SignalKind) -> Self {
+        Self::new(kind.as_raw_value())
+    }
+}
+
+impl From<SignalId> for unix::SignalKind {
+    fn from(id: SignalId) -> Self {
+        unix::SignalKind::from_raw(id.number)
+    }
+}
+
+impl fmt::Display for SignalId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.number)
+    }
+}
+
+fn signal_name(number: libc::c_int) -> &'static str {
+    match number {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGCHLD => "SIGCHLD",
+        libc::SIGCONT => "SIGCONT",
+        libc::SIGSTOP => "SIGSTOP",
+        libc::SIGTSTP => "SIGTSTP",
+        libc::SIGTTIN => "SIGTTIN",
+        libc::SIGTTOU => "SIGTTOU",
+        libc::SIGURG => "SIGURG",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        libc::SIGVTALRM => "SIGVTALRM",
+        libc::SIGPROF => "SIGPROF",
+        libc::SIGWINCH => "SIGWINCH",
+        libc::SIGIO => "SIGIO",
+        libc::SIGSYS => "SIGSYS",
+        _ => "UNKNOWN",
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SignalError {
+    #[error("failed to subscribe on signal {signal}")]
+    Subscribe {
+        signal: SignalId,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// Returns a stream which yields every received signal of the specified kinds.
+///
+/// Listeners are registered immediately, so signals received before the first poll
+/// are not lost.
+///
+/// # Panics
+///
+/// Panics if subscription fails, see [`try_signal_stream`].
+pub fn signal_stream<I, T>(signals: I) -> SignalStream
+where
+    I: IntoIterator<Item = T>,
+    T: Into<unix::SignalKind>,
+{
+    try_signal_stream(signals).expect("Failed subscribing on unix signals")
+}
+
+/// Same as [`signal_stream`], but returns an error if any of the signals
+/// can't be subscribed on.
+pub fn try_signal_stream<I, T>(signals: I) -> Result<SignalStream, SignalError>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<unix::SignalKind>,
+{
+    let signals = signals
+        .into_iter()
+        .map(|signal| {
+            let signal = SignalId::from(signal.into());
+            match unix::signal(signal.into()) {
+                Ok(listener) => Ok((signal, listener)),
+                Err(source) => Err(SignalError::Subscribe { signal, source }),
+            }
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(SignalStream { signals, next: 0 })
+}
+
+/// Stream of received signals, see [`signal_stream`].
+///
+/// Closed listeners are dropped, the stream ends when none are left.
+pub struct SignalStream {
+    signals: Vec<(SignalId, unix::Signal)>,
+    next: usize,
+}
+
+impl futures_util::Stream for SignalStream {
+    type Item = SignalId;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        // Start from the next listener each time so that frequent signals
+        // don't starve others
+        let mut remaining = this.signals.len();
+        while remaining > 0 {
+            remaining -= 1;
+
+            let index = this.next % this.signals.len();
+            let (signal, listener) = &mut this.signals[index];
+            match listener.poll_recv(cx) {
+                Poll::Ready(Some(())) => {
+                    this.next = index + 1;
+                    return Poll::Ready(Some(*signal));
+                }
+                Poll::Ready(None) => {
+                    // NOTE: the next listener is shifted to this index
+                    drop(this.signals.remove(index));
+                    this.next = index;
+                }
+                Poll::Pending => this.next = index + 1,
+            }
+        }
+
+        if this.signals.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Returns a receiver which resolves on the first of the specified signals.
+///
+/// # Panics
+///
+/// Panics if subscription fails, see [`try_any_signal`].
+pub fn any_signal<I, T>(signals: I) -> tokio::sync::oneshot::Receiver<SignalId>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<unix::SignalKind>,
+{
+    try_any_signal(signals).expect("Failed subscribing on unix signals")
+}
+
+/// Same as [`any_signal`], but returns an error if any of the signals
+/// can't be subscribed on.
+pub fn try_any_signal<I, T>(
+    signals: I,
+) -> Result<tokio::sync::oneshot::Receiver<SignalId>, SignalError>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<unix::SignalKind>,
+{
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    let mut signals = try_signal_stream(signals)?;
+    tokio::spawn(async move {
+        if let Some(signal) = signals.next().await {
+            tx.send(signal).ok();
+        }
+    });
+
+    Ok(rx)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn repeated_signals() {
+        let mut signals = signal_stream([
+            unix::SignalKind::hangup(),
+            unix::SignalKind::user_defined1(),
+        ]);
+
+        for signal in [libc::SIGHUP, libc::SIGUSR1, libc::SIGHUP] {
+            unsafe { libc::raise(signal) };
+            let received = signals.next().await.unwrap();
+            assert_eq!(received.number, signal);
+        }
+        assert_eq!(SignalId::new(libc::SIGHUP).to_string(), "SIGHUP (1)");
+    }
+
+    #[tokio::test]
+    async fn empty_stream() {
+        let mut signals = signal_stream(Vec::<SignalId>::new());
+        assert!(signals.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn forbidden_signal() {
+        let err = try_any_signal([libc::SIGTERM, libc::SIGKILL]).unwrap_err();
+        let SignalError::Subscribe { signal, .. } = err;
+        assert_eq!(signal.name, "SIGKILL");
+    }
+}
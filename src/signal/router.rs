@@ -0,0 +1,249 @@
+use std::collections::hash_map::{Entry, HashMap};
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::{Arc, Mutex, Weak};
+
+use futures_util::future::BoxFuture;
+use futures_util::StreamExt;
+use tokio::signal::unix;
+use tokio::task::JoinHandle;
+
+use super::{try_signal_stream, SignalError, SignalId};
+
+/// How handlers of the same signal are executed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HandlerMode {
+    /// One after another in the registration order.
+    ///
+    /// The next signal is not handled until all handlers of the previous one
+    /// finish, so a handler which hangs blocks all later signals.
+    #[default]
+    Sequential,
+    /// All at once.
+    Concurrent,
+}
+
+/// Registry of async signal handlers.
+///
+/// Each signal has a single listener which is shared by all its handlers.
+/// The listener is stopped when the last handler of the signal is unregistered
+/// or when all clones of the router are dropped.
+///
+/// Handler errors and panics are logged.
+#[derive(Clone, Default)]
+pub struct SignalRouter {
+    mode: HandlerMode,
+    state: Arc<Mutex<RouterState>>,
+}
+
+impl SignalRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Execution mode of handlers. Default: [`HandlerMode::Sequential`].
+    pub fn with_mode(mut self, mode: HandlerMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Registers a handler for the signal.
+    ///
+    /// Subscribes to the signal if this is its first handler.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn register<S, F, Fut, E>(&self, signal: S, handler: F) -> Result<HandlerId, SignalError>
+    where
+        S: Into<unix::SignalKind>,
+        F: Fn(SignalId) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Display + 'static,
+    {
+        let signal = SignalId::from(signal.into());
+        let handler: Handler = Arc::new(move |signal| {
+            let fut = handler(signal);
+            Box::pin(async move { fut.await.map_err(|e| e.to_string()) })
+        });
+
+        let mut state = self.state.lock().unwrap();
+        let id = HandlerId {
+            signal: signal.number,
+            id: state.next_id,
+        };
+        state.next_id += 1;
+
+        let route = match state.routes.entry(signal.number) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(Route {
+                handlers: Vec::new(),
+                listener: spawn_listener(signal, self.mode, Arc::downgrade(&self.state))?,
+            }),
+        };
+        route.handlers.push((id.id, handler));
+
+        Ok(id)
+    }
+
+    /// Removes the handler. Returns `false` if it was already removed.
+    ///
+    /// Unsubscribes from the signal if this was its last handler.
+    pub fn unregister(&self, id: HandlerId) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let route = match state.routes.get_mut(&id.signal) {
+            Some(route) => route,
+            None => return false,
+        };
+
+        let len = route.handlers.len();
+        route
+            .handlers
+            .retain(|(handler_id, _)| *handler_id != id.id);
+        let removed = route.handlers.len() < len;
+
+        if route.handlers.is_empty() {
+            // NOTE: the listener is aborted on drop
+            state.routes.remove(&id.signal);
+        }
+        removed
+    }
+
+    /// Returns the number of handlers registered for the signal.
+    pub fn handler_count<S: Into<unix::SignalKind>>(&self, signal: S) -> usize {
+        let signal = signal.into().as_raw_value();
+        let state = self.state.lock().unwrap();
+        state
+            .routes
+            .get(&signal)
+            .map(|route| route.handlers.len())
+            .unwrap_or_default()
+    }
+}
+
+/// Identifier of the handler registered in the [`SignalRouter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HandlerId {
+    signal: libc::c_int,
+    id: u64,
+}
+
+impl HandlerId {
+    /// Returns the signal of the handler.
+    pub fn signal(&self) -> SignalId {
+        SignalId::new(self.signal)
+    }
+}
+
+fn spawn_listener(
+    signal: SignalId,
+    mode: HandlerMode,
+    state: Weak<Mutex<RouterState>>,
+) -> Result<JoinHandle<()>, SignalError> {
+    let mut signals = try_signal_stream([signal])?;
+
+    Ok(tokio::spawn(async move {
+        while let Some(signal) = signals.next().await {
+            let handlers = match state.upgrade() {
+                Some(state) => match state.lock().unwrap().routes.get(&signal.number) {
+                    Some(route) => route.handlers.clone(),
+                    None => break,
+                },
+                None => break,
+            };
+
+            let tasks = handlers
+                .into_iter()
+                .map(|(_, handler)| tokio::spawn(handler(signal)));
+
+            match mode {
+                HandlerMode::Sequential => {
+                    for task in tasks {
+                        log_result(signal, task.await);
+                    }
+                }
+                HandlerMode::Concurrent => {
+                    for task in tasks {
+                        tokio::spawn(async move { log_result(signal, task.await) });
+                    }
+                }
+            }
+        }
+    }))
+}
+
+fn log_result(signal: SignalId, result: Result<Result<(), String>, tokio::task::JoinError>) {
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => log::error!("{signal} handler failed: {e}"),
+        Err(e) if e.is_panic() => log::error!("{signal} handler panicked"),
+        Err(_) => {}
+    }
+}
+
+type Handler = Arc<dyn Fn(SignalId) -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+#[derive(Default)]
+struct RouterState {
+    next_id: u64,
+    routes: HashMap<libc::c_int, Route>,
+}
+
+struct Route {
+    handlers: Vec<(u64, Handler)>,
+    listener: JoinHandle<()>,
+}
+
+impl Drop for Route {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn route_signals() {
+        let router = SignalRouter::new();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let reload = router
+            .register(unix::SignalKind::window_change(), {
+                let tx = tx.clone();
+                move |signal| {
+                    let tx = tx.clone();
+                    async move { tx.send(("reload", signal)).map_err(|_| "closed") }
+                }
+            })
+            .unwrap();
+        let dump = router
+            .register(libc::SIGWINCH, move |signal| {
+                let tx = tx.clone();
+                async move {
+                    tx.send(("dump", signal)).ok();
+                    Err("dump failed")
+                }
+            })
+            .unwrap();
+        assert_eq!(router.handler_count(libc::SIGWINCH), 2);
+        assert_eq!(dump.signal().name, "SIGWINCH");
+
+        unsafe { libc::raise(libc::SIGWINCH) };
+        assert_eq!(rx.recv().await.unwrap().0, "reload");
+        let (name, signal) = rx.recv().await.unwrap();
+        assert_eq!(name, "dump");
+        assert_eq!(signal.number, libc::SIGWINCH);
+
+        assert!(router.unregister(reload));
+        assert!(!router.unregister(reload));
+
+        unsafe { libc::raise(libc::SIGWINCH) };
+        assert_eq!(rx.recv().await.unwrap().0, "dump");
+
+        assert!(router.unregister(dump));
+        assert_eq!(router.handler_count(libc::SIGWINCH), 0);
+        assert!(router.state.lock().unwrap().routes.is_empty());
+    }
+}
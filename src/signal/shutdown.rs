@@ -0,0 +1,559 @@
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use futures_util::StreamExt;
+use tokio::signal::unix;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use super::{try_signal_stream, SignalError};
+
+/// Exit code used when the shutdown is interrupted by repeated signals.
+pub const ESCALATION_EXIT_CODE: i32 = 130;
+
+/// Stage of the shutdown. Phases run one after another in the declaration order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ShutdownPhase {
+    /// Stop accepting new work (e.g. close listeners).
+    Early,
+    /// Finish in-flight work.
+    Normal,
+    /// Release shared resources (e.g. flush storages).
+    Late,
+}
+
+impl ShutdownPhase {
+    pub const ALL: [ShutdownPhase; 3] = [Self::Early, Self::Normal, Self::Late];
+
+    const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Graceful shutdown coordinator.
+///
+/// Shutdown starts on [`Shutdown::trigger`] (or on a signal, see [`Shutdown::run_on_signals`])
+/// and runs registered hooks phase by phase. Within a phase hooks with a higher priority
+/// run first, hooks with the same priority run concurrently.
+///
+/// Each phase has a timeout. Hooks which didn't finish in time are aborted
+/// and listed in the [`ShutdownReport`].
+///
+/// When waiting for signals, the shutdown escalates on repeated signals:
+/// the second one within the escalation window aborts the running hooks
+/// (the remaining ones still run), the third one exits the process
+/// with [`ESCALATION_EXIT_CODE`].
+#[derive(Clone)]
+pub struct Shutdown {
+    token: CancellationToken,
+    /// Token of the hooks which are running at the moment.
+    running: Arc<Mutex<Option<CancellationToken>>>,
+    phase_tokens: Arc<[CancellationToken; 3]>,
+    hooks: Arc<Mutex<Vec<ShutdownHook>>>,
+    phase_timeouts: [Duration; 3],
+    force_exit: Option<(Duration, i32)>,
+    escalation_window: Duration,
+    escalation_exit_code: i32,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self {
+            token: CancellationToken::new(),
+            running: Default::default(),
+            phase_tokens: Default::default(),
+            hooks: Default::default(),
+            phase_timeouts: [Duration::from_secs(10); 3],
+            force_exit: None,
+            escalation_window: Duration::from_secs(10),
+            escalation_exit_code: ESCALATION_EXIT_CODE,
+        }
+    }
+
+    /// Timeout of all phases. Default: 10s.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.phase_timeouts = [timeout; 3];
+        self
+    }
+
+    /// Timeout of the specified phase. Default: 10s.
+    pub fn with_phase_timeout(mut self, phase: ShutdownPhase, timeout: Duration) -> Self {
+        self.phase_timeouts[phase.index()] = timeout;
+        self
+    }
+
+    /// Exits the process with the `code` if the shutdown takes longer than `after`.
+    pub fn with_force_exit(mut self, after: Duration, code: i32) -> Self {
+        self.force_exit = Some((after, code));
+        self
+    }
+
+    /// Max interval between repeated signals to escalate the shutdown. Default: 10s.
+    pub fn with_escalation_window(mut self, window: Duration) -> Self {
+        self.escalation_window = window;
+        self
+    }
+
+    /// Exit code used on the third signal. Default: [`ESCALATION_EXIT_CODE`].
+    pub fn with_escalation_exit_code(mut self, code: i32) -> Self {
+        self.escalation_exit_code = code;
+        self
+    }
+
+    /// Returns a token which is cancelled when the shutdown starts.
+    pub fn token(&self) -> CancellationToken {
+        self.token.child_token()
+    }
+
+    /// Returns a token which is cancelled when the specified phase starts.
+    pub fn phase_token(&self, phase: ShutdownPhase) -> CancellationToken {
+        self.phase_tokens[phase.index()].child_token()
+    }
+
+    /// Starts the shutdown.
+    pub fn trigger(&self) {
+        self.token.cancel();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Aborts the hooks which are running at the moment, the remaining hooks still run.
+    ///
+    /// Starts the shutdown if it wasn't started yet.
+    pub fn abort(&self) {
+        if let Some(running) = &*self.running.lock().unwrap() {
+            running.cancel();
+        }
+        self.token.cancel();
+    }
+
+    /// Registers a hook with the default priority (`0`).
+    ///
+    /// Returns `false` if the shutdown has already started.
+    pub fn register<F, Fut>(&self, name: impl Into<String>, phase: ShutdownPhase, hook: F) -> bool
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.register_with_priority(name, phase, 0, hook)
+    }
+
+    /// Registers a hook. Hooks with a higher priority run first within the phase.
+    ///
+    /// Hooks registered after the shutdown has started are ignored,
+    /// in that case `false` is returned.
+    pub fn register_with_priority<F, Fut>(
+        &self,
+        name: impl Into<String>,
+        phase: ShutdownPhase,
+        priority: i32,
+        hook: F,
+    ) -> bool
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        let name = name.into();
+
+        // NOTE: checked under the lock, because hooks are taken
+        // after the shutdown has started
+        let mut hooks = self.hooks.lock().unwrap();
+        if self.is_triggered() {
+            log::warn!("shutdown hook `{name}` is registered too late and will not run");
+            return false;
+        }
+
+        hooks.push(ShutdownHook {
+            name,
+            phase,
+            priority,
+            hook: Box::new(move || Box::pin(hook())),
+        });
+        true
+    }
+
+    /// Waits for any of the signals or a manual trigger and runs the shutdown.
+    ///
+    /// Signals received during the shutdown escalate it (see [`Shutdown`]).
+    ///
+    /// Fails if any of the signals can't be subscribed on.
+    pub async fn run_on_signals<I, T>(self, signals: I) -> Result<ShutdownReport, SignalError>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<unix::SignalKind>,
+    {
+        let mut signals = try_signal_stream(signals)?;
+        let mut escalation = Escalation::new(self.escalation_window);
+        tokio::select! {
+            Some(signal) = signals.next() => {
+                log::info!("received signal {signal}, shutting down");
+                escalation.on_signal(Instant::now());
+                self.trigger();
+            }
+            _ = self.token.cancelled() => {}
+        }
+
+        let listener = tokio::spawn({
+            let shutdown = self.clone();
+            async move {
+                while let Some(signal) = signals.next().await {
+                    match escalation.on_signal(Instant::now()) {
+                        EscalationStep::Graceful => {
+                            log::info!("received signal {signal}, shutdown is in progress");
+                        }
+                        EscalationStep::Abort => {
+                            log::warn!("received signal {signal} again, aborting shutdown hooks");
+                            shutdown.abort();
+                        }
+                        EscalationStep::Exit => {
+                            log::error!("received signal {signal} three times, exiting");
+                            std::process::exit(shutdown.escalation_exit_code);
+                        }
+                    }
+                }
+            }
+        });
+
+        let report = self.run().await;
+        listener.abort();
+        Ok(report)
+    }
+
+    /// Waits for the trigger and runs all registered hooks.
+    pub async fn run(self) -> ShutdownReport {
+        self.token.cancelled().await;
+
+        let started_at = Instant::now();
+        let _force_exit = self
+            .force_exit
+            .map(|(after, code)| spawn_force_exit(after, code));
+
+        let mut hooks = std::mem::take(&mut *self.hooks.lock().unwrap());
+        hooks.sort_by_key(|hook| (hook.phase, std::cmp::Reverse(hook.priority)));
+
+        let mut report = ShutdownReport::default();
+        let mut hooks = hooks.into_iter().peekable();
+        for phase in ShutdownPhase::ALL {
+            self.phase_tokens[phase.index()].cancel();
+            let deadline = Instant::now() + self.phase_timeouts[phase.index()];
+
+            while let Some(priority) = hooks
+                .peek()
+                .filter(|hook| hook.phase == phase)
+                .map(|hook| hook.priority)
+            {
+                let mut group = Vec::new();
+                while let Some(hook) = hooks.next_if(|h| h.phase == phase && h.priority == priority)
+                {
+                    group.push(hook);
+                }
+
+                if Instant::now() >= deadline {
+                    report
+                        .timed_out
+                        .extend(group.into_iter().map(|hook| hook.name));
+                    continue;
+                }
+
+                let abort = CancellationToken::new();
+                *self.running.lock().unwrap() = Some(abort.clone());
+
+                let tasks = group
+                    .into_iter()
+                    .map(|hook| (hook.name, tokio::spawn((hook.hook)())))
+                    .collect::<Vec<_>>();
+                wait_group(tasks, deadline, &abort, &mut report).await;
+
+                *self.running.lock().unwrap() = None;
+            }
+        }
+
+        report.elapsed = started_at.elapsed();
+        if report.is_complete() {
+            log::info!("shutdown finished in {:?}", report.elapsed);
+        } else {
+            log::warn!(
+                "shutdown finished in {:?}, timed out: {:?}, aborted: {:?}, panicked: {:?}",
+                report.elapsed,
+                report.timed_out,
+                report.aborted,
+                report.panicked
+            );
+        }
+        report
+    }
+}
+
+async fn wait_group(
+    tasks: Vec<(String, JoinHandle<()>)>,
+    deadline: Instant,
+    abort: &CancellationToken,
+    report: &mut ShutdownReport,
+) {
+    for (name, mut task) in tasks {
+        let result = tokio::select! {
+            // NOTE: hooks which have already finished are not reported as aborted
+            biased;
+            result = tokio::time::timeout_at(deadline, &mut task) => result,
+            _ = abort.cancelled() => {
+                task.abort();
+                report.aborted.push(name);
+                continue;
+            }
+        };
+
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) if e.is_panic() => {
+                log::error!("shutdown hook `{name}` panicked");
+                report.panicked.push(name);
+            }
+            Ok(Err(_)) => report.aborted.push(name),
+            Err(_) => {
+                log::warn!("shutdown hook `{name}` didn't finish in time");
+                task.abort();
+                report.timed_out.push(name);
+            }
+        }
+    }
+}
+
+/// Exits the process from a separate thread unless the returned sender is dropped in time.
+fn spawn_force_exit(after: Duration, code: i32) -> mpsc::Sender<()> {
+    let (tx, rx) = mpsc::channel::<()>();
+    std::thread::spawn(move || {
+        if let Err(mpsc::RecvTimeoutError::Timeout) = rx.recv_timeout(after) {
+            log::error!("shutdown didn't finish in {after:?}, exiting");
+            std::process::exit(code);
+        }
+    });
+    tx
+}
+
+/// Counts repeated signals within the window.
+struct Escalation {
+    window: Duration,
+    count: usize,
+    last: Option<Instant>,
+}
+
+impl Escalation {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            count: 0,
+            last: None,
+        }
+    }
+
+    fn on_signal(&mut self, now: Instant) -> EscalationStep {
+        match self.last {
+            Some(last) if now.saturating_duration_since(last) <= self.window => self.count += 1,
+            _ => self.count = 1,
+        }
+        self.last = Some(now);
+
+        match self.count {
+            1 => EscalationStep::Graceful,
+            2 => EscalationStep::Abort,
+            _ => EscalationStep::Exit,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EscalationStep {
+    Graceful,
+    Abort,
+    Exit,
+}
+
+struct ShutdownHook {
+    name: String,
+    phase: ShutdownPhase,
+    priority: i32,
+    hook: Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>,
+}
+
+/// Result of the [`Shutdown`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Total shutdown duration.
+    pub elapsed: Duration,
+    /// Names of hooks which didn't finish before the phase timeout.
+    pub timed_out: Vec<String>,
+    /// Names of hooks which were aborted by [`Shutdown::abort`].
+    pub aborted: Vec<String>,
+    /// Names of hooks which panicked.
+    pub panicked: Vec<String>,
+}
+
+impl ShutdownReport {
+    /// Returns whether all hooks finished successfully.
+    pub fn is_complete(&self) -> bool {
+        self.timed_out.is_empty() && self.aborted.is_empty() && self.panicked.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn phases_and_timeouts() {
+        let shutdown =
+            Shutdown::new().with_phase_timeout(ShutdownPhase::Normal, Duration::from_millis(50));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let push = |name: &'static str| {
+            let order = order.clone();
+            move || async move { order.lock().unwrap().push(name) }
+        };
+        shutdown.register("cleanup", ShutdownPhase::Late, push("cleanup"));
+        shutdown.register("drain", ShutdownPhase::Normal, push("drain"));
+        shutdown.register_with_priority("listener", ShutdownPhase::Early, -1, push("listener"));
+        shutdown.register_with_priority("flush", ShutdownPhase::Early, 1, push("flush"));
+        shutdown.register_with_priority("stuck", ShutdownPhase::Normal, 1, || {
+            std::future::pending::<()>()
+        });
+        shutdown.register_with_priority("panic", ShutdownPhase::Late, 1, || async {
+            panic!("hook failed")
+        });
+
+        let token = shutdown.token();
+        let late_token = shutdown.phase_token(ShutdownPhase::Late);
+        let child = token.child_token();
+        assert!(!child.is_cancelled());
+
+        let run = tokio::spawn(shutdown.clone().run());
+        shutdown.trigger();
+        let report = run.await.unwrap();
+
+        assert!(child.is_cancelled());
+        assert!(late_token.is_cancelled());
+        assert_eq!(*order.lock().unwrap(), ["flush", "listener", "cleanup"]);
+        assert_eq!(report.timed_out, ["stuck", "drain"]);
+        assert_eq!(report.panicked, ["panic"]);
+        assert!(!report.is_complete());
+    }
+
+    #[tokio::test]
+    async fn abort_hooks() {
+        let shutdown = Shutdown::new();
+        shutdown.register("stuck", ShutdownPhase::Normal, std::future::pending::<()>);
+        shutdown.register("cleanup", ShutdownPhase::Late, || async {});
+
+        let run = tokio::spawn(shutdown.clone().run());
+        shutdown.trigger();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        shutdown.abort();
+
+        let report = run.await.unwrap();
+        assert_eq!(report.aborted, ["stuck"]);
+        assert!(report.timed_out.is_empty());
+    }
+
+    #[tokio::test]
+    async fn abort_finished_hooks() {
+        let done = tokio::spawn(async {});
+        while !done.is_finished() {
+            tokio::task::yield_now().await;
+        }
+        let stuck = tokio::spawn(std::future::pending::<()>());
+
+        let abort = CancellationToken::new();
+        abort.cancel();
+
+        let mut report = ShutdownReport::default();
+        let tasks = vec![("done".to_owned(), done), ("stuck".to_owned(), stuck)];
+        let deadline = Instant::now() + Duration::from_secs(10);
+        wait_group(tasks, deadline, &abort, &mut report).await;
+
+        assert_eq!(report.aborted, ["stuck"]);
+        assert!(report.timed_out.is_empty());
+    }
+
+    #[tokio::test]
+    async fn escalate_on_signals() {
+        let shutdown = Shutdown::new();
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        shutdown.register("stuck", ShutdownPhase::Normal, move || async move {
+            started_tx.send(()).ok();
+            std::future::pending::<()>().await
+        });
+        let cleaned_up = Arc::new(Mutex::new(false));
+        shutdown.register("cleanup", ShutdownPhase::Late, {
+            let cleaned_up = cleaned_up.clone();
+            move || async move { *cleaned_up.lock().unwrap() = true }
+        });
+
+        // Install the handler before the shutdown task subscribes on the signal
+        let _signals = try_signal_stream([libc::SIGUSR2]).unwrap();
+        let run = tokio::spawn(shutdown.run_on_signals([libc::SIGUSR2]));
+
+        unsafe { libc::raise(libc::SIGUSR2) };
+        started_rx.await.unwrap();
+        unsafe { libc::raise(libc::SIGUSR2) };
+
+        let report = run.await.unwrap().unwrap();
+        assert_eq!(report.aborted, ["stuck"]);
+        assert!(report.timed_out.is_empty());
+        assert!(*cleaned_up.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn register_after_trigger() {
+        let shutdown = Shutdown::new();
+        assert!(shutdown.register("early", ShutdownPhase::Normal, || async {}));
+
+        shutdown.trigger();
+        let ran = Arc::new(Mutex::new(false));
+        assert!(!shutdown.register("late", ShutdownPhase::Normal, {
+            let ran = ran.clone();
+            move || async move { *ran.lock().unwrap() = true }
+        }));
+
+        let report = shutdown.run().await;
+        assert!(report.is_complete());
+        assert!(!*ran.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn forbidden_shutdown_signal() {
+        let shutdown = Shutdown::new();
+        let err = shutdown.run_on_signals([libc::SIGKILL]).await.unwrap_err();
+        let SignalError::Subscribe { signal, .. } = err;
+        assert_eq!(signal.number, libc::SIGKILL);
+    }
+
+    #[test]
+    fn escalation_steps() {
+        let window = Duration::from_secs(1);
+        let mut escalation = Escalation::new(window);
+        let start = Instant::now();
+
+        assert_eq!(escalation.on_signal(start), EscalationStep::Graceful);
+        assert_eq!(
+            escalation.on_signal(start + window * 2),
+            EscalationStep::Graceful
+        );
+        assert_eq!(
+            escalation.on_signal(start + window * 3),
+            EscalationStep::Abort
+        );
+        assert_eq!(
+            escalation.on_signal(start + window * 4),
+            EscalationStep::Exit
+        );
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::{Layered, SubscriberExt};
+use tracing_subscriber::{reload, Layer, Registry};
+
+type FilteredRegistry = Layered<reload::Layer<Targets, Registry>, Registry>;
+type BoxedLayer = Box<dyn Layer<FilteredRegistry> + Send + Sync>;
+
+/// Initializes a global `tracing` subscriber.
+///
+/// Returns a handle which can be used to change log levels at runtime.
+/// The handle must be kept alive while logging into a file.
+pub fn init_tracing(config: &TracingConfig) -> Result<TracingHandle, TracingError> {
+    let (subscriber, handle) = build_subscriber(config)?;
+    // NOTE: the bridge is installed first, because it only forwards records
+    // to the current subscriber and is harmless if the subscriber fails
+    if config.log_bridge {
+        tracing_log::LogTracer::init()?;
+    }
+    tracing::subscriber::set_global_default(subscriber)?;
+    Ok(handle)
+}
+
+fn build_subscriber(
+    config: &TracingConfig,
+) -> Result<(impl tracing::Subscriber + Send + Sync, TracingHandle), TracingError> {
+    let (filter, filter_handle) = reload::Layer::new(config.targets());
+
+    let mut layers = Vec::<BoxedLayer>::new();
+    if config.console {
+        layers.push(fmt_layer(config.format, config.ansi, std::io::stdout));
+    }
+
+    let mut guard = None;
+    if let Some(file) = &config.file {
+        let mut builder = RollingFileAppender::builder()
+            .rotation(file.rotation.into())
+            .filename_prefix(&file.prefix);
+        if let Some(suffix) = &file.suffix {
+            builder = builder.filename_suffix(suffix);
+        }
+        if let Some(max_files) = file.max_files {
+            builder = builder.max_log_files(max_files);
+        }
+
+        let (writer, worker_guard) =
+            tracing_appender::non_blocking(builder.build(&file.directory)?);
+        guard = Some(Arc::new(worker_guard));
+        layers.push(fmt_layer(
+            file.format.unwrap_or(config.format),
+            false,
+            writer,
+        ));
+    }
+
+    let subscriber = tracing_subscriber::registry().with(filter).with(layers);
+    let handle = TracingHandle {
+        filter: filter_handle,
+        _guard: guard,
+    };
+    Ok((subscriber, handle))
+}
+
+fn fmt_layer<W>(format: TracingFormat, ansi: bool, writer: W) -> BoxedLayer
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_ansi(ansi)
+        .with_writer(writer);
+
+    match format {
+        TracingFormat::Full => layer.boxed(),
+        TracingFormat::Compact => layer.compact().boxed(),
+        TracingFormat::Pretty => layer.pretty().boxed(),
+        TracingFormat::Json => layer.json().boxed(),
+    }
+}
+
+/// Handle to the subscriber created by [`init_tracing`].
+#[derive(Clone)]
+pub struct TracingHandle {
+    filter: reload::Handle<Targets, Registry>,
+    _guard: Option<Arc<WorkerGuard>>,
+}
+
+impl TracingHandle {
+    /// Replaces log levels with the ones from the specified config.
+    ///
+    /// NOTE: formats and outputs can't be changed at runtime.
+    pub fn reload(&self, config: &TracingConfig) -> Result<(), TracingError> {
+        self.filter.reload(config.targets())?;
+        Ok(())
+    }
+
+    /// Overrides the log level of the specified target.
+    pub fn set_target_level<T>(&self, target: T, level: TracingLevel) -> Result<(), TracingError>
+    where
+        T: Into<String>,
+    {
+        self.filter.modify(|targets| {
+            *targets = std::mem::take(targets).with_target(target, LevelFilter::from(level));
+        })?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for TracingHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TracingHandle").finish_non_exhaustive()
+    }
+}
+
+/// `tracing` subscriber config.
+///
+/// ```yaml
+/// level: info
+/// format: compact
+/// targets:
+///   tokio: warn
+///   my_app::db: debug
+/// file:
+///   directory: logs
+///   prefix: node
+///   suffix: log
+///   rotation: daily
+///   max_files: 7
+///   format: json
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TracingConfig {
+    /// Default log level.
+    #[serde(default = "default_level")]
+    pub level: TracingLevel,
+    /// Per-target log level overrides.
+    #[serde(default)]
+    pub targets: BTreeMap<String, TracingLevel>,
+    #[serde(default)]
+    pub format: TracingFormat,
+    /// Use ANSI colors in the console output.
+    #[serde(default = "default_true")]
+    pub ansi: bool,
+    /// Write logs to stdout.
+    #[serde(default = "default_true")]
+    pub console: bool,
+    #[serde(default)]
+    pub file: Option<TracingFileConfig>,
+    /// Forward records from the `log` crate.
+    #[serde(default = "default_true")]
+    pub log_bridge: bool,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self {
+            level: default_level(),
+            targets: Default::default(),
+            format: Default::default(),
+            ansi: true,
+            console: true,
+            file: None,
+            log_bridge: true,
+        }
+    }
+}
+
+impl TracingConfig {
+    fn targets(&self) -> Targets {
+        Targets::new()
+            .with_default(LevelFilter::from(self.level))
+            .with_targets(
+                self.targets
+                    .iter()
+                    .map(|(target, level)| (target.clone(), LevelFilter::from(*level))),
+            )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TracingFileConfig {
+    pub directory: PathBuf,
+    /// File name prefix, the date is appended to it after rotation.
+    pub prefix: String,
+    #[serde(default)]
+    pub suffix: Option<String>,
+    #[serde(default)]
+    pub rotation: TracingRotation,
+    /// Max number of log files to keep.
+    #[serde(default)]
+    pub max_files: Option<usize>,
+    /// Overrides the console format.
+    #[serde(default)]
+    pub format: Option<TracingFormat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TracingLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<TracingLevel> for LevelFilter {
+    fn from(level: TracingLevel) -> Self {
+        match level {
+            TracingLevel::Off => LevelFilter::OFF,
+            TracingLevel::Error => LevelFilter::ERROR,
+            TracingLevel::Warn => LevelFilter::WARN,
+            TracingLevel::Info => LevelFilter::INFO,
+            TracingLevel::Debug => LevelFilter::DEBUG,
+            TracingLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TracingFormat {
+    #[default]
+    Full,
+    Compact,
+    Pretty,
+    Json,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TracingRotation {
+    Minutely,
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+impl From<TracingRotation> for Rotation {
+    fn from(rotation: TracingRotation) -> Self {
+        match rotation {
+            TracingRotation::Minutely => Rotation::MINUTELY,
+            TracingRotation::Hourly => Rotation::HOURLY,
+            TracingRotation::Daily => Rotation::DAILY,
+            TracingRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+fn default_level() -> TracingLevel {
+    TracingLevel::Info
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TracingError {
+    #[error("failed to create log file appender")]
+    InvalidFileAppender(#[from] tracing_appender::rolling::InitError),
+    #[error("failed to set subscriber")]
+    InitializationError(#[from] tracing::subscriber::SetGlobalDefaultError),
+    #[error("failed to set log bridge")]
+    LogBridgeError(#[from] tracing_log::log::SetLoggerError),
+    #[error("failed to reload subscriber")]
+    ReloadError(#[from] reload::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn file_output_and_reload() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let config = serde_json::from_value::<TracingConfig>(serde_json::json!({
+            "level": "info",
+            "targets": { "test_target": "warn" },
+            "console": false,
+            "file": {
+                "directory": dir.path(),
+                "prefix": "node",
+                "rotation": "never",
+                "format": "json",
+            },
+        }))
+        .unwrap();
+
+        let (subscriber, handle) = build_subscriber(&config).unwrap();
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "test_target", "skipped");
+            tracing::warn!(target: "test_target", "first");
+
+            handle
+                .set_target_level("test_target", TracingLevel::Debug)
+                .unwrap();
+            tracing::debug!(target: "test_target", "second");
+            tracing::debug!(target: "other_target", "skipped");
+        });
+        drop(handle);
+
+        let output = std::fs::read_to_string(dir.path().join("node")).unwrap();
+        let messages = output
+            .lines()
+            .map(|line| {
+                let line = serde_json::from_str::<serde_json::Value>(line).unwrap();
+                line["fields"]["message"].as_str().unwrap().to_owned()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(messages, ["first", "second"]);
+    }
+}
@@ -0,0 +1,379 @@
+use std::alloc::{GlobalAlloc, Layout};
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+/// Allocator which is wrapped by [`TrackingAllocator`] by default.
+#[cfg(feature = "alloc")]
+pub type DefaultTrackedAllocator = crate::alloc::Allocator;
+/// Allocator which is wrapped by [`TrackingAllocator`] by default.
+#[cfg(not(feature = "alloc"))]
+pub type DefaultTrackedAllocator = std::alloc::System;
+
+/// Global allocator wrapper which counts allocations, bytes and peak usage.
+///
+/// ```ignore
+/// #[global_allocator]
+/// static GLOBAL: TrackingAllocator = TrackingAllocator::new(broxus_util::alloc::allocator());
+/// ```
+///
+/// Allocations are counted in thread-local counters which are added to the
+/// global ones in batches (every 1024 events, once the usage of the thread
+/// changes by 64 KiB, and on thread exit). So the global counters lag behind
+/// by at most one batch per thread, and the peak usage is tracked with the
+/// same precision. Counters of the current thread are available through
+/// [`TrackingScope`].
+///
+/// Counters are shared by all instances, since there can be only one global
+/// allocator.
+pub struct TrackingAllocator<A = DefaultTrackedAllocator> {
+    inner: A,
+}
+
+impl<A> TrackingAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+
+    /// Returns the wrapped allocator.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Returns counters of all threads.
+    ///
+    /// Pending counters of the current thread are flushed first.
+    pub fn stats(&self) -> TrackingStats {
+        THREAD_STATE.try_with(ThreadState::flush).ok();
+
+        let counters = &GLOBAL_COUNTERS;
+        TrackingStats {
+            allocations: counters.allocations.load(Ordering::Relaxed),
+            deallocations: counters.deallocations.load(Ordering::Relaxed),
+            allocated_bytes: counters.allocated_bytes.load(Ordering::Relaxed),
+            deallocated_bytes: counters.deallocated_bytes.load(Ordering::Relaxed),
+            current_bytes: counters.current_bytes.load(Ordering::Relaxed).max(0) as u64,
+            peak_bytes: counters.peak_bytes.load(Ordering::Relaxed).max(0) as u64,
+        }
+    }
+
+    /// Resets the peak usage to the current usage.
+    pub fn reset_peak(&self) {
+        THREAD_STATE.try_with(ThreadState::flush).ok();
+
+        let counters = &GLOBAL_COUNTERS;
+        counters.peak_bytes.store(
+            counters.current_bytes.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            on_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        on_dealloc(layout.size());
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            on_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            on_dealloc(layout.size());
+            on_alloc(new_size);
+        }
+        new_ptr
+    }
+}
+
+/// Thread counters are flushed after this number of allocations and deallocations.
+const FLUSH_EVENTS: u64 = 1024;
+
+/// Thread counters are flushed once the usage of the thread changes by this
+/// number of bytes.
+const FLUSH_BYTES: u64 = 64 * 1024;
+
+fn on_alloc(size: usize) {
+    record(ThreadCounters {
+        allocations: 1,
+        allocated_bytes: size as u64,
+        current_bytes: size as i64,
+        ..ThreadCounters::ZERO
+    });
+}
+
+fn on_dealloc(size: usize) {
+    record(ThreadCounters {
+        deallocations: 1,
+        deallocated_bytes: size as u64,
+        current_bytes: -(size as i64),
+        ..ThreadCounters::ZERO
+    });
+}
+
+fn record(event: ThreadCounters) {
+    if THREAD_STATE.try_with(|state| state.record(&event)).is_err() {
+        // NOTE: thread-local state is no longer available
+        GLOBAL_COUNTERS.add(&event);
+    }
+}
+
+/// Counters of the [`TrackingAllocator`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrackingStats {
+    pub allocations: u64,
+    pub deallocations: u64,
+    pub allocated_bytes: u64,
+    pub deallocated_bytes: u64,
+    pub current_bytes: u64,
+    pub peak_bytes: u64,
+}
+
+static GLOBAL_COUNTERS: GlobalCounters = GlobalCounters {
+    allocations: AtomicU64::new(0),
+    deallocations: AtomicU64::new(0),
+    allocated_bytes: AtomicU64::new(0),
+    deallocated_bytes: AtomicU64::new(0),
+    current_bytes: AtomicI64::new(0),
+    peak_bytes: AtomicI64::new(0),
+};
+
+struct GlobalCounters {
+    allocations: AtomicU64,
+    deallocations: AtomicU64,
+    allocated_bytes: AtomicU64,
+    deallocated_bytes: AtomicU64,
+    // NOTE: can be temporarily negative when memory is allocated
+    // and freed by different threads
+    current_bytes: AtomicI64,
+    peak_bytes: AtomicI64,
+}
+
+impl GlobalCounters {
+    /// Adds counters of the thread. The peak of the delta is the excess of
+    /// its peak usage over its final usage.
+    fn add(&self, delta: &ThreadCounters) {
+        self.allocations
+            .fetch_add(delta.allocations, Ordering::Relaxed);
+        self.deallocations
+            .fetch_add(delta.deallocations, Ordering::Relaxed);
+        self.allocated_bytes
+            .fetch_add(delta.allocated_bytes, Ordering::Relaxed);
+        self.deallocated_bytes
+            .fetch_add(delta.deallocated_bytes, Ordering::Relaxed);
+        let current = self
+            .current_bytes
+            .fetch_add(delta.current_bytes, Ordering::Relaxed)
+            + delta.current_bytes;
+        self.peak_bytes
+            .fetch_max(current + delta.peak_bytes, Ordering::Relaxed);
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct ThreadCounters {
+    allocations: u64,
+    deallocations: u64,
+    allocated_bytes: u64,
+    deallocated_bytes: u64,
+    current_bytes: i64,
+    peak_bytes: i64,
+}
+
+impl ThreadCounters {
+    const ZERO: Self = Self {
+        allocations: 0,
+        deallocations: 0,
+        allocated_bytes: 0,
+        deallocated_bytes: 0,
+        current_bytes: 0,
+        peak_bytes: 0,
+    };
+
+    /// Returns counters accumulated since `prev` (without the peak).
+    fn since(&self, prev: &Self) -> Self {
+        Self {
+            allocations: self.allocations - prev.allocations,
+            deallocations: self.deallocations - prev.deallocations,
+            allocated_bytes: self.allocated_bytes - prev.allocated_bytes,
+            deallocated_bytes: self.deallocated_bytes - prev.deallocated_bytes,
+            current_bytes: self.current_bytes - prev.current_bytes,
+            peak_bytes: 0,
+        }
+    }
+}
+
+struct ThreadState {
+    /// All counters of the thread.
+    counters: Cell<ThreadCounters>,
+    /// Counters which were already added to [`GLOBAL_COUNTERS`].
+    flushed: Cell<ThreadCounters>,
+    /// Peak usage of the thread since the last flush.
+    batch_peak: Cell<i64>,
+    guard: Cell<FlushGuardState>,
+}
+
+impl ThreadState {
+    fn record(&self, event: &ThreadCounters) {
+        let mut counters = self.counters.get();
+        counters.allocations += event.allocations;
+        counters.deallocations += event.deallocations;
+        counters.allocated_bytes += event.allocated_bytes;
+        counters.deallocated_bytes += event.deallocated_bytes;
+        counters.current_bytes += event.current_bytes;
+        counters.peak_bytes = counters.peak_bytes.max(counters.current_bytes);
+        self.counters.set(counters);
+        self.batch_peak
+            .set(self.batch_peak.get().max(counters.current_bytes));
+
+        let pending = counters.since(&self.flushed.get());
+        if pending.allocations + pending.deallocations >= FLUSH_EVENTS
+            || pending.current_bytes.unsigned_abs() >= FLUSH_BYTES
+            || self.guard.get() == FlushGuardState::Destroyed
+        {
+            self.flush();
+        }
+
+        if self.guard.get() == FlushGuardState::Uninit {
+            // NOTE: registering the destructor can allocate, these
+            // allocations are just counted without registering it again
+            self.guard.set(FlushGuardState::Registering);
+            let registered = FLUSH_GUARD.try_with(|_| {}).is_ok();
+            self.guard.set(if registered {
+                FlushGuardState::Registered
+            } else {
+                FlushGuardState::Destroyed
+            });
+        }
+    }
+
+    fn flush(&self) {
+        let counters = self.counters.get();
+        let mut delta = counters.since(&self.flushed.get());
+        delta.peak_bytes = (self.batch_peak.get() - counters.current_bytes).max(0);
+        GLOBAL_COUNTERS.add(&delta);
+        self.flushed.set(counters);
+        self.batch_peak.set(counters.current_bytes);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FlushGuardState {
+    Uninit,
+    Registering,
+    Registered,
+    Destroyed,
+}
+
+/// Flushes the thread counters on thread exit.
+struct FlushGuard;
+
+impl Drop for FlushGuard {
+    fn drop(&mut self) {
+        THREAD_STATE
+            .try_with(|state| {
+                state.flush();
+                state.guard.set(FlushGuardState::Destroyed);
+            })
+            .ok();
+    }
+}
+
+thread_local! {
+    // NOTE: has no destructor, so it stays available while other
+    // thread-locals are destroyed
+    static THREAD_STATE: ThreadState = const {
+        ThreadState {
+            counters: Cell::new(ThreadCounters::ZERO),
+            flushed: Cell::new(ThreadCounters::ZERO),
+            batch_peak: Cell::new(0),
+            guard: Cell::new(FlushGuardState::Uninit),
+        }
+    };
+
+    static FLUSH_GUARD: FlushGuard = const { FlushGuard };
+}
+
+/// Measures allocations of the current thread made through
+/// the global [`TrackingAllocator`] since the scope was created.
+///
+/// Scopes can be nested and can't be moved to another thread.
+pub struct TrackingScope {
+    start: ThreadCounters,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl TrackingScope {
+    pub fn new() -> Self {
+        let start = THREAD_STATE.with(|state| {
+            let mut c = state.counters.get();
+            let start = c;
+            // Track the peak of this scope separately, it is merged back on drop
+            c.peak_bytes = c.current_bytes;
+            state.counters.set(c);
+            start
+        });
+
+        Self {
+            start,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Returns allocations since the scope was created.
+    pub fn stats(&self) -> TrackingStats {
+        let c = THREAD_STATE.with(|state| state.counters.get());
+        TrackingStats {
+            allocations: c.allocations - self.start.allocations,
+            deallocations: c.deallocations - self.start.deallocations,
+            allocated_bytes: c.allocated_bytes - self.start.allocated_bytes,
+            deallocated_bytes: c.deallocated_bytes - self.start.deallocated_bytes,
+            current_bytes: (c.current_bytes - self.start.current_bytes).max(0) as u64,
+            peak_bytes: (c.peak_bytes - self.start.current_bytes).max(0) as u64,
+        }
+    }
+}
+
+impl Default for TrackingScope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TrackingScope {
+    fn drop(&mut self) {
+        THREAD_STATE
+            .try_with(|state| {
+                let mut c = state.counters.get();
+                c.peak_bytes = c.peak_bytes.max(self.start.peak_bytes);
+                state.counters.set(c);
+            })
+            .ok();
+    }
+}
+
+/// Runs the closure and returns its result with the allocations made inside it.
+pub fn track_allocations<F, R>(f: F) -> (R, TrackingStats)
+where
+    F: FnOnce() -> R,
+{
+    let scope = TrackingScope::new();
+    let result = f();
+    (result, scope.stats())
+}
@@ -0,0 +1,39 @@
+#![cfg(feature = "tracing")]
+
+use broxus_util::{init_tracing, TracingConfig, TracingError};
+
+#[test]
+fn init_with_log_bridge() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let config = serde_json::from_value::<TracingConfig>(serde_json::json!({
+        "console": false,
+        "log_bridge": true,
+        "file": {
+            "directory": dir.path(),
+            "prefix": "node",
+            "rotation": "never",
+            "format": "json",
+        },
+    }))
+    .unwrap();
+
+    let handle = init_tracing(&config).unwrap();
+    tracing_log::log::warn!("from log");
+
+    // Nothing is replaced on the second attempt
+    let err = init_tracing(&config).unwrap_err();
+    assert!(matches!(err, TracingError::LogBridgeError(_)));
+    tracing::warn!("from tracing");
+    drop(handle);
+
+    let output = std::fs::read_to_string(dir.path().join("node")).unwrap();
+    let messages = output
+        .lines()
+        .map(|line| {
+            let line = serde_json::from_str::<serde_json::Value>(line).unwrap();
+            line["fields"]["message"].as_str().unwrap().to_owned()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(messages, ["from log", "from tracing"]);
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "alloc-tracking")]
+
+use broxus_util::{track_allocations, TrackingAllocator, TrackingScope};
+
+#[global_allocator]
+static GLOBAL: TrackingAllocator<std::alloc::System> = TrackingAllocator::new(std::alloc::System);
+
+#[test]
+fn track_scopes() {
+    let (_, stats) = track_allocations(|| {
+        let data = vec![0u8; 1000];
+        assert_eq!(data.len(), 1000);
+    });
+    assert_eq!(stats.allocations, 1);
+    assert_eq!(stats.deallocations, 1);
+    assert_eq!(stats.allocated_bytes, 1000);
+    assert_eq!(stats.current_bytes, 0);
+    assert_eq!(stats.peak_bytes, 1000);
+
+    let outer = TrackingScope::new();
+    let first = vec![0u8; 2000];
+    {
+        let inner = TrackingScope::new();
+        let second = vec![0u8; 500];
+        drop(second);
+        assert_eq!(inner.stats().peak_bytes, 500);
+    }
+    drop(first);
+    let stats = outer.stats();
+    assert_eq!(stats.allocated_bytes, 2500);
+    assert_eq!(stats.peak_bytes, 2500);
+
+    assert!(GLOBAL.stats().allocated_bytes >= 3500);
+
+    // Counters of other threads can lag behind by one batch (64 KiB)
+    GLOBAL.reset_peak();
+    let big = vec![0u8; 1 << 20];
+    drop(big);
+    let stats = GLOBAL.stats();
+    assert!(stats.allocated_bytes >= 3500 + (1 << 20));
+    assert!(stats.peak_bytes >= (1 << 20) - (64 << 10));
+    assert!(stats.current_bytes < 64 << 10);
+}
+
+#[test]
+fn flush_on_thread_exit() {
+    let before = GLOBAL.stats();
+    std::thread::spawn(|| {
+        for i in 0..100 {
+            let data = vec![0u8; 16 + i];
+            assert_eq!(data.len(), 16 + i);
+        }
+    })
+    .join()
+    .unwrap();
+
+    let stats = GLOBAL.stats();
+    assert!(stats.allocations >= before.allocations + 100);
+    assert!(stats.deallocations >= before.deallocations + 100);
+}